@@ -1,7 +1,141 @@
+pub const DEFAULT_CUSTOM_SEARCH_BASE_URL: &str = "https://www.googleapis.com";
+pub const DEFAULT_CUSTOM_SEARCH_API_VERSION: &str = "v1";
+pub const DEFAULT_GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+pub const DEFAULT_GEMINI_API_VERSION: &str = "v1beta";
+
 pub const GEMINI_MODEL_FLASH: &str = "gemini-1.5-flash-latest";
 pub const GEMINI_MODEL_PRO: &str = "gemini-1.5-pro-latest";
 pub const GEMINI_MODEL_EXPERIMENTAL: &str = "gemini-1.5-pro-exp-0801";
 
+pub struct DefaultModel {
+    pub name: &'static str,
+    /// Input context window in tokens
+    pub context_window: u64,
+    /// USD per million input tokens
+    pub input_price: f64,
+    /// USD per million output tokens
+    pub output_price: f64,
+    pub json_mode: bool,
+    pub tools: bool,
+    pub streaming: bool,
+    pub deprecated: bool,
+    /// Models to try, in order, when this one is deprecated, overloaded or not allowed
+    pub fallback: &'static [&'static str],
+}
+
+/// Built-in model registry, overridable with the `MODEL_REGISTRY` env variable.
+/// Models not listed here are matched to the entry with the longest matching name prefix.
+pub const DEFAULT_MODEL_REGISTRY: &[DefaultModel] = &[
+    DefaultModel {
+        name: GEMINI_MODEL_FLASH,
+        context_window: 1_048_576,
+        input_price: 0.075,
+        output_price: 0.30,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.5-flash"],
+    },
+    DefaultModel {
+        name: GEMINI_MODEL_PRO,
+        context_window: 2_097_152,
+        input_price: 1.25,
+        output_price: 5.00,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.5-pro"],
+    },
+    DefaultModel {
+        name: GEMINI_MODEL_EXPERIMENTAL,
+        context_window: 2_097_152,
+        input_price: 1.25,
+        output_price: 5.00,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: true,
+        fallback: &[GEMINI_MODEL_PRO],
+    },
+    DefaultModel {
+        name: "gemini-1.5-flash",
+        context_window: 1_048_576,
+        input_price: 0.075,
+        output_price: 0.30,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.5-flash"],
+    },
+    DefaultModel {
+        name: "gemini-1.5-pro",
+        context_window: 2_097_152,
+        input_price: 1.25,
+        output_price: 5.00,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.5-pro"],
+    },
+    DefaultModel {
+        name: "gemini-2.0-flash",
+        context_window: 1_048_576,
+        input_price: 0.10,
+        output_price: 0.40,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &[],
+    },
+    DefaultModel {
+        name: "gemini-2.5-flash",
+        context_window: 1_048_576,
+        input_price: 0.30,
+        output_price: 2.50,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.0-flash"],
+    },
+    DefaultModel {
+        name: "gemini-2.5-pro",
+        context_window: 1_048_576,
+        input_price: 1.25,
+        output_price: 10.00,
+        json_mode: true,
+        tools: true,
+        streaming: true,
+        deprecated: false,
+        fallback: &["gemini-2.5-flash"],
+    },
+];
+pub const DEFAULT_CONTEXT_WINDOW: u64 = 128_000;
+/// Tokens kept free in the context window for the answer
+pub const CONTEXT_WINDOW_OUTPUT_RESERVE: u64 = 8_192;
+/// Prompts whose ~4 characters per token estimate is at least this many times
+/// under the limit (so even one token per character fits) skip `countTokens`
+pub const LOCAL_TOKEN_ESTIMATE_MARGIN: u64 = 4;
+/// Default maximum number of search rounds in research mode
+pub const RESEARCH_MAX_ITERATIONS: usize = 4;
+/// Characters of each result's website content shown to the research review model
+pub const RESEARCH_REVIEW_CONTENT_CHARS: usize = 2_000;
+/// Minimum share of a claim's words (and all of its numbers) that must appear in a
+/// source passage for the claim to count as supported
+pub const VERIFICATION_SUPPORT_THRESHOLD: f64 = 0.6;
+/// USD per Custom Search JSON API query ($5 per 1000 queries)
+pub const DEFAULT_SEARCH_QUERY_PRICE: f64 = 0.005;
+/// USD per Gemini request grounded with Google Search ($35 per 1000 requests)
+pub const DEFAULT_GROUNDED_QUERY_PRICE: f64 = 0.035;
+/// Output token cap sent with every Gemini request unless `MAX_OUTPUT_TOKENS` is
+/// set, and assumed in full when checking `max_cost` before a call
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u64 = 8_192;
+
 pub const SEARCH_QUERY_OPTIMISATION_PROMPT: &str =
 "You are a search optimisation AI that takes a natural language query and returns an optimised search query.
 You should return an optimised search query that will return the most relevant results.
@@ -50,8 +184,63 @@ Use real sources to support the information.
 Obey the laws of physics, mathematics, and the laws of the universe. (real world distances, angles, etc.)
 Think carefully before providing any information.
 Take into consideration the liklihood, credibility, and reliability of the information.
+Search results may have a credibility hint (high or low) based on the reputation of their domain.
+Only return the most relevant content, do not return anything else.";
+
+pub const SUMMARIZE_DEFAULT_QUESTION: &str = "Summarise the key information in these pages.";
+
+pub const GROUNDED_SEARCH_PROMPT: &str = "You are a search AI that answers a natural language query using Google Search.
+Search for the most relevant and up to date information that will answer the query.
+ONLY USE factual information found in the search results and DO NOT make up information.
+Obey the laws of physics, mathematics, and the laws of the universe. (real world distances, angles, etc.)
+Think carefully before providing any information.
+Take into consideration the liklihood, credibility, and reliability of the information.
 Only return the most relevant content, do not return anything else.";
 
+pub const RESEARCH_REVIEW_PROMPT: &str = "You are a research AI that reviews the evidence gathered so far for a natural language query and decides whether more searching is needed.
+You are given the query, the search queries already made and the evidence (search results and the start of their website content).
+If the evidence is enough to answer the query fully and accurately, you are confident.
+If it is not, return up to 3 new search queries that would find the missing information. Do not repeat a search query that has already been made.
+Take into consideration the liklihood, credibility, and reliability of the information.
+Return JSON only, in the following format:
+
+{\"confident\": true | false, \"reasoning\": \"{what is known and what is missing}\", \"follow_up_queries\": [\"{query_1}\", \"{query_2}\"]}";
+
+pub const RESEARCH_REPORT_PROMPT: &str = "You are a research AI that takes a natural language query, a trace of the searches made while researching it and the search results found, and writes a long-form report answering the query.
+ONLY USE factual information and DO NOT make up information.
+Use real sources to support the information and cite the source of every fact.
+Obey the laws of physics, mathematics, and the laws of the universe. (real world distances, angles, etc.)
+Think carefully before providing any information.
+Take into consideration the liklihood, credibility, and reliability of the information.
+Search results may have a credibility hint (high or low) based on the reputation of their domain.
+Point out where sources disagree or where the information could not be found.";
+
+pub const RESEARCH_FORMATTING_PROMPT: &str = "
+Return a markdown report with the following template:
+
+# {title}
+
+{summary}
+
+## {section_1}
+
+{section_1_information}
+
+## {section_2}
+
+{section_2_information}
+
+## Conclusion
+
+{conclusion}
+
+## Sources
+
+1. [{source_1_title}]({source_1})
+2. [{source_2_title}]({source_2})
+
+Query:";
+
 pub const CUSTOM_FORMATTING_PROMPT: &str = "
 Return optimised markdown content with the following template:
 
@@ -92,11 +281,135 @@ Sources:
 
 Query:";
 
-pub const DISALLOWED_URLS: &[&str] = &[
-    "https://www.reddit.com",
-    "https://x.com",
-    "https://www.twitter.com",
-    "https://www.instagram.com",
-    "https://www.youtube.com",
-    "https://www.tiktok.com",
+/// Domains (and their subdomains) that are not scraped, only their snippets are used,
+/// unless they have an enabled site-specific extractor
+pub const DISALLOWED_DOMAINS: &[&str] = &[
+    "reddit.com",
+    "x.com",
+    "twitter.com",
+    "instagram.com",
+    "youtube.com",
+    "tiktok.com",
+];
+
+/// (domain, extractor name) for sites with a site-specific extractor, see `SiteExtractor`
+pub const SITE_EXTRACTOR_DOMAINS: &[(&str, &str)] = &[
+    ("reddit.com", "reddit"),
+    ("youtube.com", "youtube"),
+    ("youtu.be", "youtube"),
+    ("x.com", "x"),
+    ("twitter.com", "x"),
+    ("instagram.com", "instagram"),
+    ("tiktok.com", "tiktok"),
+];
+/// Maximum number of Reddit comments kept, highest score first
+pub const REDDIT_MAX_COMMENTS: usize = 50;
+
+/// Content types that are scraped, anything else is skipped
+pub const ALLOWED_CONTENT_TYPES: &[&str] = &[
+    "text/html",
+    "application/xhtml+xml",
+    "text/plain",
+    "application/xml",
+    "text/xml",
+];
+/// Content types read by the JSON site extractors (Reddit, oEmbed)
+pub const JSON_CONTENT_TYPES: &[&str] = &["application/json", "text/json"];
+/// Bodies are truncated at this size, or skipped if their Content-Length is larger
+pub const DEFAULT_SCRAPE_MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+pub const DEFAULT_SCRAPE_MAX_REDIRECTS: usize = 5;
+pub const DEFAULT_SCRAPE_TIMEOUT_MS: u64 = 10_000;
+
+/// Static scrapes with less text than this are rendered with the headless browser
+/// backend, for domains opted in with `RENDER_DOMAINS`
+pub const DEFAULT_RENDER_MIN_TEXT_LENGTH: usize = 500;
+pub const DEFAULT_RENDER_TIMEOUT_MS: u64 = 15_000;
+pub const DEFAULT_CDP_ENDPOINT: &str = "http://127.0.0.1:9222";
+
+/// (domain pattern, reputation from -1.0 to 1.0) used to boost or penalise
+/// sources when ranking, overridable with the `DOMAIN_REPUTATION` env variable.
+/// The most specific matching pattern wins.
+pub const DEFAULT_DOMAIN_REPUTATION: &[(&str, f64)] = &[
+    ("*.gov", 0.3),
+    ("*.gov.au", 0.3),
+    ("*.gov.uk", 0.3),
+    ("*.edu", 0.3),
+    ("who.int", 0.3),
+    ("wikipedia.org", 0.2),
+    ("reuters.com", 0.3),
+    ("apnews.com", 0.3),
+    ("bbc.co.uk", 0.2),
+    ("bbc.com", 0.2),
+    ("nature.com", 0.3),
+    ("sec.gov", 0.4),
+    ("quora.com", -0.2),
+    ("pinterest.com", -0.4),
+    ("answers.com", -0.3),
 ];
+/// Reputation at or above which a result is hinted to Gemini as high credibility
+/// (and at or below the negative of which, low credibility)
+pub const CREDIBILITY_HINT_THRESHOLD: f64 = 0.2;
+
+/// Where `FIXTURE_MODE=record` saves upstream responses and `FIXTURE_MODE=replay` reads them
+pub const DEFAULT_FIXTURE_DIR: &str = "./fixtures";
+
+/// `service.name` reported to the OTLP collector unless `OTEL_SERVICE_NAME` is set
+pub const DEFAULT_OTEL_SERVICE_NAME: &str = "googlexity";
+
+/// Paths served without `API_KEY`: health checks for load balancers and
+/// `/metrics`, which has its own optional `METRICS_TOKEN`
+pub const AUTH_EXEMPT_PATHS: &[&str] = &["/healthz", "/readyz", "/version", "/metrics"];
+/// Realm in the `WWW-Authenticate` challenge of rejected requests
+pub const AUTH_REALM: &str = "googlexity";
+/// Env variables `/readyz` requires before reporting ready
+pub const REQUIRED_CONFIG: &[&str] = &[
+    "API_KEY",
+    "SEARCH_API_KEY",
+    "SEARCH_ENGINE_ID",
+    "GEMINI_API_KEY",
+];
+/// Time limit for each upstream probe of `/readyz?probe=true`
+pub const READINESS_PROBE_TIMEOUT_MS: u64 = 3_000;
+/// Rate-limit tier of API-key callers and of tokens whose claims don't map to one
+pub const DEFAULT_RATE_LIMIT_TIER: &str = "default";
+/// How long a fetched JWKS is used before it's fetched again. Tokens with an
+/// unknown `kid` refetch it sooner, at most once per `JWKS_MIN_REFRESH_SECS`.
+pub const JWKS_CACHE_TTL_SECS: u64 = 3_600;
+pub const JWKS_MIN_REFRESH_SECS: u64 = 30;
+pub const JWKS_FETCH_TIMEOUT_MS: u64 = 5_000;
+/// Clock skew allowed when checking `exp` and `nbf`
+pub const JWT_LEEWAY_SECS: u64 = 60;
+
+/// Request headers browsers may send cross-origin, unless `CORS_ALLOWED_HEADERS` is set
+pub const DEFAULT_CORS_ALLOWED_HEADERS: &[&str] =
+    &["content-type", "x-api-key", "authorization", "x-request-id"];
+/// Response headers exposed to cross-origin scripts
+pub const CORS_EXPOSED_HEADERS: &[&str] = &["x-request-id", "retry-after", "www-authenticate"];
+/// How long browsers may cache a preflight, unless `CORS_MAX_AGE` is set
+pub const DEFAULT_CORS_MAX_AGE_SECS: usize = 3_600;
+
+/// Searches running at once across all callers, unless `SEARCH_MAX_CONCURRENCY` is set
+pub const DEFAULT_SEARCH_MAX_CONCURRENCY: usize = 16;
+/// Searches running at once per API key or token subject, unless
+/// `SEARCH_MAX_CONCURRENCY_PER_KEY` or the caller's tier in `SEARCH_TIER_CONCURRENCY` says otherwise
+pub const DEFAULT_SEARCH_MAX_CONCURRENCY_PER_KEY: usize = 4;
+/// Searches waiting for a slot before new ones are turned away, unless `SEARCH_QUEUE_SIZE` is set
+pub const DEFAULT_SEARCH_QUEUE_SIZE: usize = 32;
+/// How long a search waits for a slot, unless `SEARCH_QUEUE_TIMEOUT_MS` is set
+pub const DEFAULT_SEARCH_QUEUE_TIMEOUT_MS: u64 = 10_000;
+/// `Retry-After` sent with 503s when the queue is full or the wait timed out
+pub const SEARCH_RETRY_AFTER_SECS: u64 = 5;
+
+/// Search jobs running at once, unless `SEARCH_JOB_WORKERS` is set (read at the first job)
+pub const DEFAULT_SEARCH_JOB_WORKERS: usize = 4;
+/// Queued and running jobs before new ones are turned away, unless `SEARCH_JOB_MAX_PENDING` is set
+pub const DEFAULT_SEARCH_JOB_MAX_PENDING: usize = 100;
+/// Queued and running jobs per API key or token subject, unless `SEARCH_JOB_MAX_PENDING_PER_KEY` is set
+pub const DEFAULT_SEARCH_JOB_MAX_PENDING_PER_KEY: usize = 10;
+/// How long finished jobs can be polled before they're dropped
+pub const SEARCH_JOB_RETENTION_SECS: u64 = 3_600;
+/// Webhook deliveries are retried with exponential backoff from 1 second
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 3;
+pub const WEBHOOK_TIMEOUT_MS: u64 = 10_000;
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-googlexity-signature";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-googlexity-timestamp";
@@ -4,10 +4,19 @@ pub fn is_development() -> bool {
 
 pub fn log_query(query: &str) {
     if is_development() {
-        println!("{:?}", query);
+        tracing::info!("{:?}", query);
     }
 }
 
+/// Logs a single-line JSON entry so it can be picked up by log aggregators.
+pub fn log_event(event: &str, fields: serde_json::Value) {
+    let mut entry = serde_json::json!({ "event": event });
+    if let (Some(entry), serde_json::Value::Object(fields)) = (entry.as_object_mut(), fields) {
+        entry.extend(fields);
+    }
+    tracing::info!(event, "{}", entry);
+}
+
 pub fn log_error(error: &str) {
-    eprintln!("{:?}", error);
+    tracing::error!("{:?}", error);
 }
@@ -0,0 +1,5 @@
+pub mod constants;
+pub mod middleware;
+pub mod models;
+pub mod routes;
+pub mod services;
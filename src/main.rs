@@ -1,23 +1,9 @@
 use actix_web::middleware::Logger;
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{App, HttpServer};
 use dotenv::dotenv;
-use env_logger::Env;
-
-mod constants;
-mod middleware;
-mod models;
-mod routes;
-mod services;
-
-#[get("/")]
-async fn hello() -> impl Responder {
-    HttpResponse::Ok().body("Hello world!")
-}
-
-#[post("/echo")]
-async fn echo(req_body: String) -> impl Responder {
-    HttpResponse::Ok().body(req_body)
-}
+use rust_actix_web_template::services::telemetry::Telemetry;
+use rust_actix_web_template::services::web_scraping::WebScraping;
+use rust_actix_web_template::{middleware, routes};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -26,21 +12,23 @@ async fn main() -> std::io::Result<()> {
         .unwrap_or_else(|_| "8080".to_string()) // Default to 8080 if PORT is not set
         .parse::<u16>()
         .expect("PORT must be a valid number");
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    let telemetry = Telemetry::init();
+    // Fail at startup, not on the first scrape, if the scraping client can't be built
+    WebScraping::client();
 
-    HttpServer::new(|| {
+    let result = HttpServer::new(|| {
         App::new()
             .wrap(middleware::guard_middleware::ApiKeyMiddleware)
+            .wrap(middleware::cors_middleware::CorsMiddleware::from_env())
+            .wrap(middleware::metrics_middleware::MetricsMiddleware)
             .wrap(Logger::new("%a %{User-Agent}i %r %s %b %T")) // Single, more detailed logger
-            .service(web::scope("/api").configure(|r| {
-                r.route("/search", web::post().to(routes::googlexity::search));
-                r.route(
-                    "/generate-content",
-                    web::post().to(routes::googlexity::google_ai_completion),
-                );
-            }))
+            .wrap(middleware::tracing_middleware::TracingMiddleware)
+            .configure(routes::configure)
     })
     .bind(("0.0.0.0", port))?
     .run()
-    .await
+    .await;
+
+    telemetry.shutdown();
+    result
 }
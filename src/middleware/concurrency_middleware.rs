@@ -0,0 +1,99 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{Error, InternalError},
+    http::header::RETRY_AFTER,
+    HttpMessage, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use serde_json::json;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use crate::constants::config::{DEFAULT_RATE_LIMIT_TIER, SEARCH_RETRY_AFTER_SECS};
+use crate::constants::utility::log_event;
+use crate::models::auth_models::Principal;
+use crate::services::concurrency::{ConcurrencyLimits, ConcurrencyRejection};
+use crate::services::metrics::Metrics;
+
+/// Runs the wrapped handler only once the caller's and the global concurrency
+/// limits have room (see `ConcurrencyLimits`), otherwise 503 with `Retry-After`.
+/// Callers are told apart by the `Principal` from `ApiKeyMiddleware`.
+pub struct ConcurrencyLimitMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for ConcurrencyLimitMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConcurrencyLimitMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ConcurrencyLimitMiddlewareService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ConcurrencyLimitMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ConcurrencyLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let (subject, tier) = match req.extensions().get::<Principal>() {
+            Some(principal) => (principal.subject.clone(), principal.tier.clone()),
+            None => ("anonymous".to_string(), DEFAULT_RATE_LIMIT_TIER.to_string()),
+        };
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            match ConcurrencyLimits::acquire(&subject, &tier).await {
+                Ok(_permit) => service.call(req).await,
+                Err(rejection) => {
+                    Metrics::record_rate_limit_rejection(rejection.scope());
+                    log_event(
+                        "concurrency_limited",
+                        json!({
+                            "reason": rejection.reason(),
+                            "scope": rejection.scope(),
+                            "subject": subject,
+                            "tier": tier,
+                            "path": req.path(),
+                        }),
+                    );
+                    Err(Self::rejection_error(rejection))
+                }
+            }
+        })
+    }
+}
+
+impl<S> ConcurrencyLimitMiddlewareService<S> {
+    fn rejection_error(rejection: ConcurrencyRejection) -> Error {
+        let message = rejection.message();
+        let response = HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, SEARCH_RETRY_AFTER_SECS.to_string()))
+            .json(json!({
+                "error": rejection.reason(),
+                "scope": rejection.scope(),
+                "message": message,
+            }));
+        InternalError::from_response(message, response).into()
+    }
+}
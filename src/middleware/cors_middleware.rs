@@ -0,0 +1,96 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use actix_web::middleware::Condition;
+use std::str::FromStr;
+
+use crate::constants::config::{
+    CORS_EXPOSED_HEADERS, DEFAULT_CORS_ALLOWED_HEADERS, DEFAULT_CORS_MAX_AGE_SECS,
+};
+use crate::constants::utility::log_error;
+use crate::services::domain_filtering::DomainFiltering;
+
+/// CORS for browser clients, wrapped outside `ApiKeyMiddleware` so preflight
+/// `OPTIONS` requests are answered without an API key and rejections still
+/// carry the CORS headers. Disabled unless `CORS_ALLOWED_ORIGINS` is set.
+pub struct CorsMiddleware;
+
+impl CorsMiddleware {
+    /// Configured with `CORS_ALLOWED_ORIGINS` (comma separated origins such as
+    /// `https://app.example.com`, globs such as `https://*.example.com`, or `*`),
+    /// `CORS_ALLOWED_HEADERS` and `CORS_MAX_AGE` (seconds).
+    pub fn from_env() -> Condition<Cors> {
+        let allowed_origins = Self::list("CORS_ALLOWED_ORIGINS").unwrap_or_default();
+        let allowed_headers: Vec<HeaderName> = Self::list("CORS_ALLOWED_HEADERS")
+            .unwrap_or_else(|| {
+                DEFAULT_CORS_ALLOWED_HEADERS
+                    .iter()
+                    .map(|header| header.to_string())
+                    .collect()
+            })
+            .iter()
+            .filter_map(|header| match HeaderName::from_str(header) {
+                Ok(header) => Some(header),
+                Err(_) => {
+                    log_error(&format!(
+                        "Invalid header in CORS_ALLOWED_HEADERS: {}",
+                        header
+                    ));
+                    None
+                }
+            })
+            .collect();
+        let max_age = match std::env::var("CORS_MAX_AGE") {
+            Ok(max_age) if !max_age.trim().is_empty() => {
+                max_age.trim().parse().unwrap_or_else(|_| {
+                    log_error(&format!("Invalid CORS_MAX_AGE: {}", max_age));
+                    DEFAULT_CORS_MAX_AGE_SECS
+                })
+            }
+            _ => DEFAULT_CORS_MAX_AGE_SECS,
+        };
+
+        let enabled = !allowed_origins.is_empty();
+        let cors = Cors::default()
+            .allowed_origin_fn(move |origin, _| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| Self::is_allowed_origin(origin, &allowed_origins))
+            })
+            .allowed_methods([Method::GET, Method::POST, Method::OPTIONS])
+            .allowed_headers(allowed_headers)
+            .expose_headers(CORS_EXPOSED_HEADERS.iter().copied())
+            .max_age(max_age);
+
+        Condition::new(enabled, cors)
+    }
+
+    fn is_allowed_origin(origin: &str, allowed_origins: &[String]) -> bool {
+        allowed_origins.iter().any(|allowed| {
+            if allowed == "*" || allowed.eq_ignore_ascii_case(origin) {
+                return true;
+            }
+            match (allowed.split_once("://"), origin.split_once("://")) {
+                (Some((allowed_scheme, allowed_host)), Some((scheme, host)))
+                    if allowed_host.contains('*') =>
+                {
+                    allowed_scheme.eq_ignore_ascii_case(scheme)
+                        && DomainFiltering::matches(&host.to_lowercase(), allowed_host)
+                }
+                _ => false,
+            }
+        })
+    }
+
+    fn list(name: &str) -> Option<Vec<String>> {
+        std::env::var(name)
+            .ok()
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().trim_end_matches('/').to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .filter(|items| !items.is_empty())
+    }
+}
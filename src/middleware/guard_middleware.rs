@@ -1,15 +1,29 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::Error,
-    error::ErrorUnauthorized,
+    error::{Error, InternalError},
+    http::header::{HeaderMap, AUTHORIZATION, WWW_AUTHENTICATE},
+    HttpMessage, HttpResponse,
 };
 use futures_util::future::LocalBoxFuture;
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use crate::constants::config::{AUTH_EXEMPT_PATHS, AUTH_REALM, DEFAULT_RATE_LIMIT_TIER};
+use crate::constants::utility::{log_error, log_event};
+use crate::models::auth_models::{AuthMethod, Principal};
+use crate::services::jwt_auth::{JwtAuth, JwtError};
+
+/// Authenticates requests with the `API_KEY` (in an `x-api-key` or
+/// `Authorization: Bearer` header) and/or a JWT bearer token, depending on
+/// `AUTH_MODES`, except on `AUTH_EXEMPT_PATHS`. The caller's `Principal` is
+/// added to the request extensions.
 pub struct ApiKeyMiddleware;
 
 impl<S, B> Transform<S, ServiceRequest> for ApiKeyMiddleware
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -20,17 +34,19 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(ApiKeyMiddlewareService { service }))
+        ready(Ok(ApiKeyMiddlewareService {
+            service: Rc::new(service),
+        }))
     }
 }
 
 pub struct ApiKeyMiddlewareService<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for ApiKeyMiddlewareService<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -41,19 +57,222 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let api_key = std::env::var("API_KEY").expect("API_KEY must be set");
-
-        if let Some(key) = req.headers().get("x-api-key") {
-            let key = key.to_str().unwrap();
-            if key == api_key {
-                let fut = self.service.call(req);
-                return Box::pin(async move {
-                    let res = fut.await?;
-                    Ok(res)
-                });
+        if AUTH_EXEMPT_PATHS.contains(&req.path()) {
+            let fut = self.service.call(req);
+            return Box::pin(fut);
+        }
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            match ApiKeyMiddleware::authenticate(&req).await {
+                Ok(principal) => {
+                    req.extensions_mut().insert(principal);
+                    service.call(req).await
+                }
+                Err(failure) => {
+                    let connection_info = req.connection_info();
+                    log_event(
+                        "auth_failure",
+                        json!({
+                            "reason": failure.code(),
+                            "detail": failure.detail(),
+                            "client_ip": connection_info.realip_remote_addr(),
+                            "peer_addr": connection_info.peer_addr(),
+                            "method": req.method().as_str(),
+                            "path": req.path(),
+                        }),
+                    );
+                    Err(failure.into_error())
+                }
+            }
+        })
+    }
+}
+
+/// Which credentials are accepted, from the `AUTH_MODES` env variable (comma
+/// separated `api_key` and `jwt`, defaulting to `api_key`).
+#[derive(Debug, Clone, Copy)]
+pub struct AuthModes {
+    pub api_key: bool,
+    pub jwt: bool,
+}
+
+impl AuthModes {
+    pub fn from_env() -> Self {
+        match std::env::var("AUTH_MODES") {
+            Ok(modes) if !modes.trim().is_empty() => {
+                let enabled = |mode: &str| {
+                    modes
+                        .split(',')
+                        .any(|name| name.trim().eq_ignore_ascii_case(mode))
+                };
+                AuthModes {
+                    api_key: enabled("api_key"),
+                    jwt: enabled("jwt"),
+                }
+            }
+            _ => AuthModes {
+                api_key: true,
+                jwt: false,
+            },
+        }
+    }
+}
+
+impl ApiKeyMiddleware {
+    /// The caller's API key or token from `x-api-key` or `Authorization: Bearer <key>`.
+    pub fn api_key(headers: &HeaderMap) -> Option<&str> {
+        if let Some(key) = headers.get("x-api-key") {
+            return key.to_str().ok().map(|key| key.trim());
+        }
+
+        let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = authorization.trim().split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("Bearer")
+            .then_some(token.trim())
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Bearer tokens shaped like a JWT are verified as one when `jwt` is
+    /// enabled; anything else is compared against the `API_KEY`.
+    async fn authenticate(req: &ServiceRequest) -> Result<Principal, AuthFailure> {
+        let modes = AuthModes::from_env();
+        let Some(credential) = Self::api_key(req.headers()) else {
+            // Something was sent that isn't a usable key, e.g. a non-ASCII
+            // `x-api-key` or a non-Bearer `Authorization` header.
+            let has_credentials = req.headers().contains_key("x-api-key")
+                || req.headers().contains_key(AUTHORIZATION);
+            return Err(if has_credentials {
+                AuthFailure::InvalidApiKey
+            } else {
+                AuthFailure::MissingApiKey
+            });
+        };
+
+        if modes.jwt && JwtAuth::is_jwt(credential) {
+            return JwtAuth::verify(credential).await.map_err(|e| match e {
+                JwtError::MissingScopes(scopes) => AuthFailure::InsufficientScope(scopes),
+                JwtError::KeyUnavailable(_) => AuthFailure::KeysUnavailable(e.to_string()),
+                e => AuthFailure::InvalidToken(e.to_string()),
+            });
+        }
+        if !modes.api_key {
+            return Err(AuthFailure::InvalidToken(
+                "API keys are disabled".to_string(),
+            ));
+        }
+
+        match std::env::var("API_KEY") {
+            Ok(api_key) if !api_key.is_empty() => {
+                if constant_time_eq(credential.as_bytes(), api_key.as_bytes()) {
+                    Ok(Self::api_key_principal(credential))
+                } else {
+                    Err(AuthFailure::InvalidApiKey)
+                }
             }
+            _ => {
+                log_error("API_KEY is not set, rejecting all API key requests");
+                Err(AuthFailure::NotConfigured)
+            }
+        }
+    }
+
+    /// API-key callers are identified by a hash prefix so the key itself isn't
+    /// logged or kept around.
+    fn api_key_principal(key: &str) -> Principal {
+        let hash = Sha256::digest(key.as_bytes());
+        let key_id: String = hash[..6]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Principal {
+            method: AuthMethod::ApiKey,
+            subject: format!("key:{}", key_id),
+            scopes: Vec::new(),
+            tier: DEFAULT_RATE_LIMIT_TIER.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum AuthFailure {
+    MissingApiKey,
+    InvalidApiKey,
+    NotConfigured,
+    InvalidToken(String),
+    InsufficientScope(Vec<String>),
+    /// The JWKS or key file couldn't be loaded
+    KeysUnavailable(String),
+}
+
+impl AuthFailure {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::MissingApiKey => "missing_api_key",
+            Self::InvalidApiKey => "invalid_api_key",
+            Self::NotConfigured => "auth_not_configured",
+            Self::InvalidToken(_) => "invalid_token",
+            Self::InsufficientScope(_) => "insufficient_scope",
+            Self::KeysUnavailable(_) => "auth_unavailable",
         }
+    }
 
-        Box::pin(async move { Err(ErrorUnauthorized("Invalid API Key")) })
+    fn detail(&self) -> Option<String> {
+        match self {
+            Self::InvalidToken(detail) | Self::KeysUnavailable(detail) => Some(detail.clone()),
+            Self::InsufficientScope(scopes) => Some(scopes.join(" ")),
+            _ => None,
+        }
     }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Self::MissingApiKey => {
+                "Send an API key in the x-api-key header or as Authorization: Bearer <key>"
+            }
+            Self::InvalidApiKey | Self::NotConfigured => "Invalid API Key",
+            Self::InvalidToken(_) => "Invalid or expired token",
+            Self::InsufficientScope(_) => "The token is missing required scopes",
+            Self::KeysUnavailable(_) => "Token signing keys are unavailable, try again later",
+        }
+    }
+
+    /// A 401 (403 for missing scopes, 503 when signing keys can't be loaded)
+    /// with a JSON body and an RFC 6750 `WWW-Authenticate` challenge. A missing
+    /// `API_KEY` is reported to callers as an invalid key.
+    fn into_error(self) -> Error {
+        let challenge = match &self {
+            Self::MissingApiKey | Self::KeysUnavailable(_) => {
+                format!("Bearer realm=\"{}\"", AUTH_REALM)
+            }
+            Self::InvalidApiKey | Self::NotConfigured | Self::InvalidToken(_) => {
+                format!("Bearer realm=\"{}\", error=\"invalid_token\"", AUTH_REALM)
+            }
+            Self::InsufficientScope(scopes) => format!(
+                "Bearer realm=\"{}\", error=\"insufficient_scope\", scope=\"{}\"",
+                AUTH_REALM,
+                scopes.join(" ")
+            ),
+        };
+        let code = match self {
+            Self::NotConfigured => Self::InvalidApiKey.code(),
+            _ => self.code(),
+        };
+        let mut response = match self {
+            Self::InsufficientScope(_) => HttpResponse::Forbidden(),
+            Self::KeysUnavailable(_) => HttpResponse::ServiceUnavailable(),
+            _ => HttpResponse::Unauthorized(),
+        };
+
+        let response = response
+            .insert_header((WWW_AUTHENTICATE, challenge))
+            .json(json!({ "error": code, "message": self.message() }));
+        InternalError::from_response(self.message(), response).into()
+    }
+}
+
+/// Compares keys without short-circuiting on the first differing byte.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
 }
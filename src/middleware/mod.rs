@@ -1 +1,5 @@
+pub mod concurrency_middleware;
+pub mod cors_middleware;
 pub mod guard_middleware;
+pub mod metrics_middleware;
+pub mod tracing_middleware;
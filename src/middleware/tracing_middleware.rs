@@ -0,0 +1,105 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{Error, InternalError},
+    http::header::{HeaderName, HeaderValue},
+};
+use futures_util::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Runs every request inside a `request` span carrying its request ID, which is
+/// taken from an incoming `x-request-id` header or generated, and returned in
+/// the `x-request-id` response header (including on errors from inner middleware).
+pub struct TracingMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for TracingMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = TracingMiddlewareService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TracingMiddlewareService { service }))
+    }
+}
+
+pub struct TracingMiddlewareService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for TracingMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| Self::is_valid_request_id(value))
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %req.method(),
+            path = %req.path(),
+            status = tracing::field::Empty,
+        );
+        let fut = span.in_scope(|| self.service.call(req));
+
+        Box::pin(
+            async move {
+                let request_id = HeaderValue::from_str(&request_id).ok();
+                match fut.await {
+                    Ok(mut response) => {
+                        tracing::Span::current().record("status", response.status().as_u16());
+                        if let Some(request_id) = request_id {
+                            response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+                        }
+                        Ok(response)
+                    }
+                    Err(e) => {
+                        let mut response = e.error_response();
+                        tracing::Span::current().record("status", response.status().as_u16());
+                        if let Some(request_id) = request_id {
+                            response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+                        }
+                        Err(InternalError::from_response(e, response).into())
+                    }
+                }
+            }
+            .instrument(span),
+        )
+    }
+}
+
+impl<S> TracingMiddlewareService<S> {
+    /// Caller-supplied IDs are kept only if they're short and printable, so
+    /// they can't inject anything into the logs or response headers.
+    fn is_valid_request_id(value: &str) -> bool {
+        !value.is_empty()
+            && value.len() <= 128
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+}
@@ -0,0 +1,19 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+
+/// The authenticated caller, stored in the request extensions by `ApiKeyMiddleware`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub method: AuthMethod,
+    /// The token's `sub`, or `key:<hash prefix>` for API keys
+    pub subject: String,
+    pub scopes: Vec<String>,
+    /// Rate-limit tier, `default` unless the token's claims map to another
+    pub tier: String,
+}
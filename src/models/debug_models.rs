@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::google_search_models::{ContextTrim, ScrapeSkipReason, SearchResult};
+use crate::models::usage_models::SearchUsage;
+
+/// `?mock=true` uses the mock Custom Search responses in `constants/mock/google_search/test`.
+#[derive(Debug, Deserialize)]
+pub struct DebugQuery {
+    pub mock: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScrapeRequest {
+    #[serde(default)]
+    pub urls: Vec<String>,
+}
+
+/// The optimised queries and the Custom Search results they returned.
+#[derive(Debug, Serialize)]
+pub struct RawSearchResponse {
+    pub queries: Vec<String>,
+    pub results: Vec<SearchResult>,
+    pub usage: SearchUsage,
+}
+
+/// What scraping got from one URL.
+#[derive(Debug, Serialize)]
+pub struct ScrapedPage {
+    pub link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub text_length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<ScrapeSkipReason>,
+    pub duration_ms: u128,
+}
+
+impl From<SearchResult> for ScrapedPage {
+    fn from(result: SearchResult) -> Self {
+        ScrapedPage {
+            text_length: result
+                .website_text_content
+                .as_ref()
+                .map(|text| text.len())
+                .unwrap_or(0),
+            link: result.link,
+            text: result.website_text_content,
+            skip_reason: result.skip_reason,
+            duration_ms: result.scrape_duration_ms.unwrap_or(0),
+        }
+    }
+}
+
+/// The exact prompt a search would send to the answer model, without sending it.
+#[derive(Debug, Serialize)]
+pub struct PromptPreview {
+    pub prompt: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    /// The cost so far plus the estimated prompt cost of the answer call
+    pub estimated_cost_usd: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_trim: Option<ContextTrim>,
+    /// Usage so far (query optimisation and Custom Search)
+    pub usage: SearchUsage,
+}
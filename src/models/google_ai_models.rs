@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
 
 #[derive(Deserialize)]
 pub struct AiCompletionRequest {
@@ -6,46 +7,254 @@ pub struct AiCompletionRequest {
     pub query: String,
 }
 
+/// The text of a completion along with the model that produced it and its token usage.
+#[derive(Debug)]
+pub struct AiCompletion {
+    pub text: String,
+    pub model: String,
+    pub usage_metadata: Option<UsageMetadata>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GoogleAiGenerateContentResponse {
+    #[serde(default)]
     pub candidates: Vec<Candidate>,
-    #[serde(rename = "usageMetadata")]
-    pub usage_metadata: UsageMetadata,
+    #[serde(rename = "promptFeedback", skip_serializing_if = "Option::is_none")]
+    pub prompt_feedback: Option<PromptFeedback>,
+    #[serde(rename = "usageMetadata", skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(rename = "modelVersion", skip_serializing_if = "Option::is_none")]
+    pub model_version: Option<String>,
+}
+
+impl GoogleAiGenerateContentResponse {
+    /// Joins the text parts of the first candidate, turning blocked prompts and
+    /// empty candidates into a [`GoogleAiCompletionError`] instead of panicking.
+    pub fn text(&self) -> Result<String, GoogleAiCompletionError> {
+        let candidate = match self.candidates.first() {
+            Some(candidate) => candidate,
+            None => {
+                return Err(
+                    match self
+                        .prompt_feedback
+                        .as_ref()
+                        .and_then(|feedback| feedback.block_reason.clone())
+                    {
+                        Some(reason) => GoogleAiCompletionError::PromptBlocked { reason },
+                        None => GoogleAiCompletionError::NoCandidates,
+                    },
+                );
+            }
+        };
+
+        let text = candidate
+            .content
+            .as_ref()
+            .map(|content| {
+                content
+                    .parts
+                    .iter()
+                    .filter(|part| !part.thought.unwrap_or(false))
+                    .filter_map(|part| part.text.as_deref())
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+
+        if !text.trim().is_empty() {
+            return Ok(text);
+        }
+
+        Err(match candidate.finish_reason.as_deref() {
+            Some("STOP") | Some("MAX_TOKENS") | None => GoogleAiCompletionError::EmptyCandidate {
+                finish_reason: candidate.finish_reason.clone(),
+            },
+            Some(reason) => GoogleAiCompletionError::CandidateBlocked {
+                finish_reason: reason.to_string(),
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum GoogleAiCompletionError {
+    /// The prompt itself was rejected (`promptFeedback.blockReason`) and no candidates were returned.
+    PromptBlocked { reason: String },
+    /// No candidates and no block reason were returned.
+    NoCandidates,
+    /// The candidate was stopped before producing text (e.g. `SAFETY`, `RECITATION`).
+    CandidateBlocked { finish_reason: String },
+    /// The candidate finished normally but contained no text parts.
+    EmptyCandidate { finish_reason: Option<String> },
+    /// Gemini responded with a non-success status.
+    Http { status: reqwest::StatusCode },
+    /// Every model in the fallback chain is deprecated, not allowed or lacks a required capability.
+    NoAvailableModel { requested: String },
+}
+
+impl GoogleAiCompletionError {
+    /// Whether the next model in the fallback chain should be tried: the model
+    /// is overloaded or rate limited (429/503), or no longer exists (404).
+    pub fn is_fallback_eligible(&self) -> bool {
+        match self {
+            Self::Http { status } => matches!(status.as_u16(), 404 | 429 | 503),
+            _ => false,
+        }
+    }
 }
 
+impl fmt::Display for GoogleAiCompletionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PromptBlocked { reason } => write!(f, "Prompt blocked by Gemini: {}", reason),
+            Self::NoCandidates => write!(f, "Gemini returned no candidates"),
+            Self::CandidateBlocked { finish_reason } => {
+                write!(f, "Gemini response blocked: {}", finish_reason)
+            }
+            Self::EmptyCandidate { finish_reason } => write!(
+                f,
+                "Gemini returned an empty response (finish reason: {})",
+                finish_reason.as_deref().unwrap_or("unknown")
+            ),
+            Self::Http { status } => write!(f, "HTTP error! status: {}", status),
+            Self::NoAvailableModel { requested } => {
+                write!(f, "No available model for requested model {}", requested)
+            }
+        }
+    }
+}
+
+impl Error for GoogleAiCompletionError {}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Candidate {
-    pub content: Content,
-    #[serde(rename = "finishReason")]
-    pub finish_reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<Content>,
+    #[serde(rename = "finishReason", skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
     pub index: u32,
-    #[serde(rename = "safetyRatings")]
+    #[serde(rename = "safetyRatings", default)]
     pub safety_ratings: Vec<SafetyRating>,
+    #[serde(rename = "citationMetadata", skip_serializing_if = "Option::is_none")]
+    pub citation_metadata: Option<CitationMetadata>,
+    #[serde(rename = "groundingMetadata", skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<GroundingMetadata>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Content {
+    #[serde(default)]
     pub parts: Vec<Part>,
-    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Part {
-    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SafetyRating {
     pub category: String,
     pub probability: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PromptFeedback {
+    #[serde(rename = "blockReason", skip_serializing_if = "Option::is_none")]
+    pub block_reason: Option<String>,
+    #[serde(rename = "safetyRatings", default)]
+    pub safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CitationMetadata {
+    #[serde(rename = "citationSources", default)]
+    pub citation_sources: Vec<CitationSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CitationSource {
+    #[serde(rename = "startIndex", skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<u32>,
+    #[serde(rename = "endIndex", skip_serializing_if = "Option::is_none")]
+    pub end_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroundingMetadata {
+    #[serde(rename = "webSearchQueries", default)]
+    pub web_search_queries: Vec<String>,
+    #[serde(rename = "groundingChunks", default)]
+    pub grounding_chunks: Vec<GroundingChunk>,
+    #[serde(rename = "groundingSupports", default)]
+    pub grounding_supports: Vec<GroundingSupport>,
+    #[serde(rename = "searchEntryPoint", skip_serializing_if = "Option::is_none")]
+    pub search_entry_point: Option<SearchEntryPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroundingChunk {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web: Option<WebChunk>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebChunk {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroundingSupport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment: Option<Segment>,
+    #[serde(rename = "groundingChunkIndices", default)]
+    pub grounding_chunk_indices: Vec<u32>,
+    #[serde(rename = "confidenceScores", default)]
+    pub confidence_scores: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Segment {
+    #[serde(rename = "startIndex", default)]
+    pub start_index: u32,
+    #[serde(rename = "endIndex", default)]
+    pub end_index: u32,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchEntryPoint {
+    #[serde(rename = "renderedContent", skip_serializing_if = "Option::is_none")]
+    pub rendered_content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CountTokensResponse {
+    #[serde(rename = "totalTokens", default)]
+    pub total_tokens: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UsageMetadata {
-    #[serde(rename = "promptTokenCount")]
+    #[serde(rename = "promptTokenCount", default)]
     pub prompt_token_count: u32,
-    #[serde(rename = "candidatesTokenCount")]
+    #[serde(rename = "candidatesTokenCount", default)]
     pub candidates_token_count: u32,
-    #[serde(rename = "totalTokenCount")]
+    #[serde(rename = "totalTokenCount", default)]
     pub total_token_count: u32,
 }
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, error::Error, fmt};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::constants::config::SUMMARIZE_DEFAULT_QUESTION;
+use crate::models::research_models::ResearchHop;
+use crate::models::usage_models::SearchUsage;
+use crate::services::ssrf_guard::SsrfBlocked;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
     pub model: Option<String>,
@@ -10,6 +15,144 @@ pub struct SearchRequest {
     pub custom_instructions: Option<String>,
     pub max_optimizations: Option<i32>,
     pub depthfull_search: Option<bool>,
+    pub mode: Option<SearchMode>,
+    pub max_cost: Option<f64>,
+    pub response_format: Option<ResponseFormat>,
+    pub verify_citations: Option<bool>,
+    pub max_iterations: Option<i32>,
+    pub include_domains: Option<Vec<String>>,
+    pub exclude_domains: Option<Vec<String>>,
+}
+
+/// Answer a question from pages that are already known, skipping Custom Search.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SummarizeRequest {
+    pub urls: Vec<String>,
+    pub question: Option<String>,
+    pub model: Option<String>,
+    pub custom_instructions: Option<String>,
+    pub max_cost: Option<f64>,
+    pub response_format: Option<ResponseFormat>,
+    pub verify_citations: Option<bool>,
+}
+
+impl SummarizeRequest {
+    /// The equivalent search request, so summaries share the search answer pipeline.
+    pub fn search_request(&self) -> SearchRequest {
+        SearchRequest {
+            query: self
+                .question
+                .clone()
+                .unwrap_or(SUMMARIZE_DEFAULT_QUESTION.to_string()),
+            model: self.model.clone(),
+            max_results: None,
+            optimize_query: Some(false),
+            custom_instructions: self.custom_instructions.clone(),
+            max_optimizations: None,
+            depthfull_search: Some(true),
+            mode: None,
+            max_cost: self.max_cost,
+            response_format: self.response_format,
+            verify_citations: self.verify_citations,
+            max_iterations: None,
+            include_domains: None,
+            exclude_domains: None,
+        }
+    }
+}
+
+/// Domains configured for a caller in the `DOMAIN_POLICIES` env variable.
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyDomainPolicy {
+    #[serde(default)]
+    pub include_domains: Vec<String>,
+    #[serde(default)]
+    pub exclude_domains: Vec<String>,
+}
+
+/// Policies by principal subject (`key:<hash prefix>` for API keys, `sub` for
+/// JWTs) or by `tier:<tier>`.
+pub type DomainPolicies = HashMap<String, KeyDomainPolicy>;
+
+/// The domains a search is restricted to, combined from the request and the caller's policy.
+#[derive(Debug, Default, Clone)]
+pub struct DomainPolicy {
+    pub include_domains: Vec<String>,
+    pub key_include_domains: Vec<String>,
+    pub exclude_domains: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// The markdown answer as the body, with usage totals in `x-googlexity-*` headers.
+    #[default]
+    Markdown,
+    /// A [`SearchAnswerResponse`] JSON body.
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchAnswerResponse {
+    pub answer: String,
+    /// The model that actually answered, after any fallbacks
+    pub model: String,
+    pub usage: SearchUsage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_trim: Option<ContextTrim>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<CitationReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub research_trace: Option<Vec<ResearchHop>>,
+}
+
+/// How well the claims in an answer are supported by the search results.
+#[derive(Debug, Serialize)]
+pub struct CitationReport {
+    /// Mean support score over all claims
+    pub support_score: f64,
+    pub unsupported_claims: usize,
+    /// Links in the answer that weren't in the search results
+    pub unknown_sources: Vec<String>,
+    pub claims: Vec<ClaimSupport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimSupport {
+    pub claim: String,
+    /// 0.0 to 1.0, the share of the claim's words found in the best matching passage
+    pub score: f64,
+    pub supported: bool,
+    /// Link of the result containing the best matching passage
+    pub source: Option<String>,
+    pub passage: Option<String>,
+    /// Links cited in the claim itself
+    pub cited_sources: Vec<String>,
+}
+
+/// What was removed from the search results so the answer prompt fits the model's context window.
+#[derive(Debug, Default, Serialize)]
+pub struct ContextTrim {
+    pub original_tokens: u64,
+    pub final_tokens: u64,
+    pub token_limit: u64,
+    /// Links whose scraped `website_text_content` was dropped, lowest-ranked first
+    pub dropped_website_content: Vec<String>,
+    /// Links of results dropped entirely (snippet included), lowest-ranked first
+    pub dropped_results: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Optimise, Custom Search, (optionally) scrape, then answer.
+    #[default]
+    Standard,
+    /// Let Gemini search with its built-in Google Search tool.
+    Grounded,
+    /// Iteratively search and scrape, with Gemini choosing follow-up queries,
+    /// then write a long-form report.
+    Research,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,6 +163,7 @@ pub struct SearchResponse {
     pub context: Context,
     #[serde(rename = "searchInformation")]
     pub search_information: SearchInformation,
+    #[serde(default)]
     pub items: Vec<SearchResult>,
 }
 
@@ -33,7 +177,7 @@ pub struct Url {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Queries {
     pub request: Vec<QueryInfo>,
-    #[serde(rename = "nextPage")]
+    #[serde(rename = "nextPage", default)]
     pub next_page: Vec<QueryInfo>,
 }
 
@@ -77,6 +221,15 @@ pub struct SearchResult {
     pub kind: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub website_text_content: Option<String>,
+    /// "high" or "low", from the reputation of the result's domain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credibility: Option<String>,
+    /// Why `website_text_content` couldn't be scraped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<ScrapeSkipReason>,
+    /// How long scraping the page took, reported by `/api/scrape`
+    #[serde(skip)]
+    pub scrape_duration_ms: Option<u128>,
     pub title: String,
     #[serde(rename = "htmlTitle")]
     pub html_title: String,
@@ -93,6 +246,140 @@ pub struct SearchResult {
     pub pagemap: Option<PageMap>,
 }
 
+impl SearchResult {
+    /// A result for a page that didn't come from Custom Search, e.g. a URL
+    /// passed to `/api/summarize`.
+    pub fn from_link(link: &str) -> Self {
+        let display_link = url::Url::parse(link)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default();
+
+        SearchResult {
+            kind: "customsearch#result".to_string(),
+            website_text_content: None,
+            credibility: None,
+            skip_reason: None,
+            scrape_duration_ms: None,
+            title: link.to_string(),
+            html_title: link.to_string(),
+            link: link.to_string(),
+            display_link,
+            snippet: String::new(),
+            html_snippet: String::new(),
+            formatted_url: link.to_string(),
+            html_formatted_url: link.to_string(),
+            pagemap: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ScrapeSkipReason {
+    /// The domain is in `DISALLOWED_DOMAINS`
+    Disallowed,
+    /// The host resolves to a private, loopback, link-local or metadata address
+    Blocked {
+        host: String,
+    },
+    HttpStatus {
+        status: u16,
+    },
+    TooManyRedirects,
+    UnsupportedContentType {
+        content_type: String,
+    },
+    TooLarge {
+        bytes: u64,
+    },
+    Timeout,
+    RequestFailed {
+        message: String,
+    },
+    /// The page had no text content
+    Empty,
+    /// A site-specific extractor failed
+    ExtractionFailed {
+        message: String,
+    },
+}
+
+impl ScrapeSkipReason {
+    /// The `reason` tag, e.g. `http_status`.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Disallowed => "disallowed",
+            Self::Blocked { .. } => "blocked",
+            Self::HttpStatus { .. } => "http_status",
+            Self::TooManyRedirects => "too_many_redirects",
+            Self::UnsupportedContentType { .. } => "unsupported_content_type",
+            Self::TooLarge { .. } => "too_large",
+            Self::Timeout => "timeout",
+            Self::RequestFailed { .. } => "request_failed",
+            Self::Empty => "empty",
+            Self::ExtractionFailed { .. } => "extraction_failed",
+        }
+    }
+}
+
+impl fmt::Display for ScrapeSkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disallowed => write!(f, "Domain is disallowed"),
+            Self::Blocked { host } => write!(f, "Blocked internal address: {}", host),
+            Self::HttpStatus { status } => write!(f, "HTTP error! status: {}", status),
+            Self::TooManyRedirects => write!(f, "Too many redirects"),
+            Self::UnsupportedContentType { content_type } => {
+                write!(f, "Unsupported content type: {}", content_type)
+            }
+            Self::TooLarge { bytes } => write!(f, "Body too large: {} bytes", bytes),
+            Self::Timeout => write!(f, "Request timed out"),
+            Self::RequestFailed { message } => write!(f, "Request failed: {}", message),
+            Self::Empty => write!(f, "No text content"),
+            Self::ExtractionFailed { message } => write!(f, "Extraction failed: {}", message),
+        }
+    }
+}
+
+impl Error for ScrapeSkipReason {}
+
+impl From<&SsrfBlocked> for ScrapeSkipReason {
+    fn from(blocked: &SsrfBlocked) -> Self {
+        Self::Blocked {
+            host: blocked.host.clone(),
+        }
+    }
+}
+
+impl From<SsrfBlocked> for ScrapeSkipReason {
+    fn from(blocked: SsrfBlocked) -> Self {
+        (&blocked).into()
+    }
+}
+
+impl From<reqwest::Error> for ScrapeSkipReason {
+    fn from(e: reqwest::Error) -> Self {
+        let mut source = e.source();
+        while let Some(cause) = source {
+            if let Some(blocked) = cause.downcast_ref::<SsrfBlocked>() {
+                return blocked.into();
+            }
+            source = cause.source();
+        }
+
+        if e.is_timeout() {
+            Self::Timeout
+        } else if e.is_redirect() {
+            Self::TooManyRedirects
+        } else {
+            Self::RequestFailed {
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PageMap {
     #[serde(skip_serializing_if = "Option::is_none")]
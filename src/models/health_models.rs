@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// `?probe=true` also checks that the upstream APIs are reachable.
+#[derive(Debug, Deserialize)]
+pub struct ReadinessQuery {
+    pub probe: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Required env variables that aren't set
+    pub missing_config: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstreams: Option<Vec<UpstreamProbe>>,
+}
+
+/// Whether an upstream answered at all; any HTTP status counts as reachable.
+#[derive(Debug, Serialize)]
+pub struct UpstreamProbe {
+    pub upstream: String,
+    pub url: String,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub name: String,
+    pub version: String,
+    /// Set with the `GIT_COMMIT` env variable at build time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+    pub providers: Providers,
+}
+
+/// The search, model and scraping backends this instance is configured with.
+#[derive(Debug, Serialize)]
+pub struct Providers {
+    pub search: String,
+    pub llm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render_backend: Option<String>,
+    pub site_extractors: Vec<String>,
+    pub fixture_mode: String,
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::google_search_models::SearchRequest;
+
+/// A [`SearchRequest`] to run in the background, with an optional webhook
+/// called when it finishes.
+#[derive(Debug, Deserialize)]
+pub struct SearchJobRequest {
+    #[serde(flatten)]
+    pub search: SearchRequest,
+    pub callback_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A pipeline stage the job has finished, see `StageTiming::observe`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageProgress {
+    pub stage: String,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub delivered: bool,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchJob {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    pub stages: Vec<StageProgress>,
+    /// The `SearchAnswerResponse`, once succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The error response or message, once failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookDelivery>,
+    /// The caller that created the job; other callers can't see it
+    #[serde(skip)]
+    pub subject: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchJobCreated {
+    pub id: String,
+    pub status: JobStatus,
+    pub status_url: String,
+}
@@ -1,2 +1,8 @@
+pub mod auth_models;
+pub mod debug_models;
 pub mod google_ai_models;
 pub mod google_search_models;
+pub mod health_models;
+pub mod job_models;
+pub mod research_models;
+pub mod usage_models;
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// The review model's verdict on the evidence gathered so far.
+#[derive(Debug, Default, Deserialize)]
+pub struct ResearchReview {
+    #[serde(default)]
+    pub confident: bool,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub follow_up_queries: Vec<String>,
+}
+
+/// One search round of a research request.
+#[derive(Debug, Serialize)]
+pub struct ResearchHop {
+    pub iteration: usize,
+    pub queries: Vec<String>,
+    /// Results not already gathered in an earlier hop
+    pub new_results: usize,
+    pub confident: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    pub follow_up_queries: Vec<String>,
+    /// Why research stopped after this hop, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<ResearchStopReason>,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResearchStopReason {
+    Confident,
+    NoFollowUpQueries,
+    MaxIterations,
+    MaxCost,
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+
+use crate::models::google_ai_models::AiCompletion;
+use crate::services::pricing::Pricing;
+
+/// Token, query and cost totals accumulated over a single search request.
+#[derive(Debug, Default, Serialize)]
+pub struct SearchUsage {
+    pub prompt_tokens: u64,
+    pub candidates_tokens: u64,
+    pub total_tokens: u64,
+    pub search_queries: u32,
+    pub grounded_queries: u32,
+    pub estimated_cost_usd: f64,
+    pub models: Vec<ModelUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub candidates_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl SearchUsage {
+    pub fn record_completion(&mut self, completion: &AiCompletion) {
+        let (prompt_tokens, candidates_tokens) = match &completion.usage_metadata {
+            Some(usage) => (
+                usage.prompt_token_count as u64,
+                usage.candidates_token_count as u64,
+            ),
+            None => (0, 0),
+        };
+        let cost = Pricing::completion_cost(&completion.model, prompt_tokens, candidates_tokens);
+
+        self.prompt_tokens += prompt_tokens;
+        self.candidates_tokens += candidates_tokens;
+        self.total_tokens += prompt_tokens + candidates_tokens;
+        self.estimated_cost_usd += cost;
+
+        match self
+            .models
+            .iter_mut()
+            .find(|model_usage| model_usage.model == completion.model)
+        {
+            Some(model_usage) => {
+                model_usage.calls += 1;
+                model_usage.prompt_tokens += prompt_tokens;
+                model_usage.candidates_tokens += candidates_tokens;
+                model_usage.cost_usd += cost;
+            }
+            None => self.models.push(ModelUsage {
+                model: completion.model.clone(),
+                calls: 1,
+                prompt_tokens,
+                candidates_tokens,
+                cost_usd: cost,
+            }),
+        }
+    }
+
+    pub fn record_search_query(&mut self) {
+        self.search_queries += 1;
+        self.estimated_cost_usd += Pricing::search_query_cost();
+    }
+
+    pub fn record_grounded_query(&mut self) {
+        self.grounded_queries += 1;
+        self.estimated_cost_usd += Pricing::grounded_query_cost();
+    }
+}
@@ -1,236 +1,164 @@
-use crate::services::web_scraping::WebScraping;
-use actix_web::{web::Json, HttpResponse, Result};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::services::{
+    domain_filtering::DomainFiltering, google_ai::GoogleAi, model_registry::ModelAccess,
+    pricing::Pricing, search_pipeline::SearchPipeline, web_scraping::WebScraping,
+};
+use actix_web::{
+    web::{Json, Query},
+    HttpMessage, HttpRequest, HttpResponse, Result,
+};
 use serde_json::json;
-use std::{error::Error, time::Instant};
+use std::error::Error;
 
 use crate::constants::config::{GEMINI_MODEL_FLASH, GEMINI_MODEL_PRO};
 use crate::{
-    constants::{
-        config::{
-            CUSTOM_FORMATTING_PROMPT, MOST_RELEVANT_CONTENT_PROMPT,
-            SEARCH_QUERY_OPTIMISATION_PROMPT,
-        },
-        utility::{log_error, log_query},
+    models::auth_models::Principal,
+    models::debug_models::{
+        DebugQuery, PromptPreview, RawSearchResponse, ScrapeRequest, ScrapedPage,
     },
-    models::google_ai_models::{AiCompletionRequest, GoogleAiGenerateContentResponse},
-    models::google_search_models::{SearchRequest, SearchResponse, SearchResult},
+    models::google_ai_models::{AiCompletionRequest, GoogleAiCompletionError},
+    models::google_search_models::{SearchRequest, SearchResult, SummarizeRequest},
+    models::usage_models::SearchUsage,
 };
 
-pub async fn search(body: Json<SearchRequest>) -> Result<HttpResponse, Box<dyn Error>> {
-    let start_time = Instant::now();
+pub async fn search(
+    req: HttpRequest,
+    body: Json<SearchRequest>,
+) -> Result<HttpResponse, Box<dyn Error>> {
+    let domain_policy = DomainFiltering::policy_for_request(&req, &body);
+    SearchPipeline::search(&body, &domain_policy, &model_access(&req)).await
+}
 
-    let query = body.query.clone();
+/// Answers a question (or summarises) from the given URLs, skipping Custom Search.
+pub async fn summarize(
+    req: HttpRequest,
+    body: Json<SummarizeRequest>,
+) -> Result<HttpResponse, Box<dyn Error>> {
+    let domain_policy = DomainFiltering::policy_for_request(&req, &body.search_request());
+    SearchPipeline::summarize(&body, &domain_policy, &model_access(&req)).await
+}
 
-    let optimised_search_response = if body.optimize_query.unwrap_or(true) {
-        google_ai_completion(actix_web::web::Json(AiCompletionRequest {
-            query: SEARCH_QUERY_OPTIMISATION_PROMPT.to_string() + &query,
-            model: Some(GEMINI_MODEL_FLASH.to_string()),
-        }))
-        .await?
+/// The optimised queries and Custom Search results for a search, without
+/// scraping or answering.
+pub async fn search_raw(
+    req: HttpRequest,
+    debug_query: Query<DebugQuery>,
+    body: Json<SearchRequest>,
+) -> Result<HttpResponse, Box<dyn Error>> {
+    let mut usage = SearchUsage::default();
+    let mock = debug_query.mock.unwrap_or(false);
+    let domain_policy = DomainFiltering::policy_for_request(&req, &body);
+
+    let queries = if mock {
+        vec![body.query.clone()]
     } else {
-        query
+        SearchPipeline::optimised_search_queries(&body, &mut usage).await?
     };
+    let results =
+        SearchPipeline::search_results(&body, &domain_policy, queries.clone(), mock, &mut usage)
+            .await?;
+
+    Ok(HttpResponse::Ok().json(RawSearchResponse {
+        queries,
+        results,
+        usage,
+    }))
+}
 
-    log_query(&format!(
-        "Optimised search response: {}",
-        optimised_search_response
-    ));
-
-    let mut split_search_queries: Vec<String> = if optimised_search_response.contains(";") {
-        optimised_search_response
-            .split(';')
-            .map(|s| s.replace("\n", ""))
-            .collect()
+/// Scrapes the given URLs (or the mock search results) and reports the text,
+/// timing and skip reason for each.
+pub async fn scrape(
+    req: HttpRequest,
+    debug_query: Query<DebugQuery>,
+    body: Json<ScrapeRequest>,
+) -> Result<HttpResponse, Box<dyn Error>> {
+    let scraped_results = if debug_query.mock.unwrap_or(false) {
+        WebScraping::retrieve_relevant_search_data_mock().await?
     } else {
-        vec![optimised_search_response]
-    };
-
-    if let Some(max_optimizations) = body.max_optimizations {
-        if let Ok(max) = usize::try_from(max_optimizations) {
-            split_search_queries = split_search_queries.into_iter().take(max).collect();
-        }
-    }
-
-    log_query(&format!("Split search queries: {:?}", split_search_queries));
-
-    let mut search_results: Vec<SearchResult> = Vec::new();
-    for query in split_search_queries {
-        let search_items = google_search(&query).await?;
-        search_results.extend(search_items);
-    }
-
-    if let Some(max_results) = body.max_results {
-        if let Ok(max) = usize::try_from(max_results) {
-            search_results = search_results.into_iter().take(max).collect();
+        if body.urls.is_empty() {
+            return Ok(
+                HttpResponse::BadRequest().json(json!({ "error": "urls must not be empty" }))
+            );
         }
-    }
-
-    let search_results_text = serde_json::to_string(&search_results)?;
-    println!(
-        "Initial search results content length: {}",
-        search_results_text.len()
-    );
-
-    let updated_search_results = if body.depthfull_search.unwrap_or(false) {
-        WebScraping::retrieve_all_website_text_content(search_results).await
-    } else {
-        search_results
-    };
-
-    let stringified_search_results = serde_json::to_string(&updated_search_results)?;
-    if body.depthfull_search.unwrap_or(false) {
-        let updated_search_results_length = stringified_search_results.len();
-        println!(
-            "Updated search results content length: {}",
-            updated_search_results_length
+        let domain_policy = DomainFiltering::policy_for_request(&req, &SearchRequest::default());
+        let pages = DomainFiltering::filter(
+            body.urls
+                .iter()
+                .map(|url| SearchResult::from_link(url))
+                .collect(),
+            &domain_policy,
         );
-    }
-
-    let ai_request = AiCompletionRequest {
-        query: MOST_RELEVANT_CONTENT_PROMPT.to_string()
-            + &body
-                .custom_instructions
-                .clone()
-                .unwrap_or(CUSTOM_FORMATTING_PROMPT.to_string())
-            + "\n\nQuery:\n"
-            + &body.query.clone()
-            + "\n\nSearch Results:\n"
-            + &stringified_search_results,
-        model: Some(body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string())),
+        WebScraping::retrieve_all_website_text_content(pages).await
     };
 
-    let ai_request_length = ai_request.query.len();
-    println!("AI request length: {}", ai_request_length);
-
-    let most_relevant_search_results =
-        google_ai_completion(actix_web::web::Json(ai_request)).await?;
-
-    let end_time = Instant::now();
-    let duration = end_time.duration_since(start_time);
-    println!("Googlexity Search time taken: {:?}", duration);
-
-    Ok(HttpResponse::Ok().body(most_relevant_search_results))
+    let pages: Vec<ScrapedPage> = scraped_results.into_iter().map(ScrapedPage::from).collect();
+    Ok(HttpResponse::Ok().json(pages))
 }
 
-pub async fn google_search(query: &str) -> Result<Vec<SearchResult>, Box<dyn Error>> {
-    let search_api_key = std::env::var("SEARCH_API_KEY").unwrap();
-    let search_engine_id = std::env::var("SEARCH_ENGINE_ID").unwrap();
-    let start_time = Instant::now();
-
-    let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "Content-Type".parse::<HeaderName>().unwrap(),
-        "application/json".parse::<HeaderValue>().unwrap(),
-    );
-    let google_search_response = match client
-        .get(format!(
-            "https://www.googleapis.com/customsearch/v1?key={}&cx={}&q={}",
-            search_api_key, search_engine_id, query
-        ))
-        .headers(headers)
-        .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(e) => {
-            log_error(&format!("Request failed: {}", e));
-            return Err(format!("Request failed: {}", e).into());
-        }
+/// Runs a standard search up to the answer call and returns the exact prompt
+/// that would be sent, so prompts can be tuned without spending answer tokens.
+pub async fn prompt_preview(
+    req: HttpRequest,
+    debug_query: Query<DebugQuery>,
+    body: Json<SearchRequest>,
+) -> Result<HttpResponse, Box<dyn Error>> {
+    let mut usage = SearchUsage::default();
+    let mock = debug_query.mock.unwrap_or(false);
+    let domain_policy = DomainFiltering::policy_for_request(&req, &body);
+
+    let queries = if mock {
+        vec![body.query.clone()]
+    } else {
+        SearchPipeline::optimised_search_queries(&body, &mut usage).await?
     };
+    let search_results =
+        SearchPipeline::search_results(&body, &domain_policy, queries, mock, &mut usage).await?;
+
+    let answer_model = body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string());
+    let (prompt, _, context_trim) =
+        SearchPipeline::answer_prompt(&body, search_results, &answer_model).await?;
+    let prompt_tokens = GoogleAi::count_tokens(&answer_model, &prompt).await;
+    let estimated_cost_usd =
+        usage.estimated_cost_usd + Pricing::estimate_completion_cost(&answer_model, &prompt);
+
+    Ok(HttpResponse::Ok().json(PromptPreview {
+        prompt,
+        model: answer_model,
+        prompt_tokens,
+        estimated_cost_usd,
+        context_trim,
+        usage,
+    }))
+}
 
-    let end_time = Instant::now();
-    let duration = end_time.duration_since(start_time);
-    println!("Google Search time taken: {:?}", duration);
-
-    if !google_search_response.status().is_success() {
-        return Err(format!("HTTP error! status: {}", google_search_response.status()).into());
+/// The models the caller may use, by the rate-limit tier of their `Principal`.
+pub fn model_access(req: &HttpRequest) -> ModelAccess {
+    match req.extensions().get::<Principal>() {
+        Some(principal) => ModelAccess::for_tier(&principal.tier),
+        None => ModelAccess::server(),
     }
-
-    let google_search_response_json: SearchResponse =
-        google_search_response.json::<SearchResponse>().await?;
-
-    let items = google_search_response_json.items;
-
-    Ok(items)
 }
 
+/// The completion text, with the model that answered (after any fallbacks)
+/// in the `x-googlexity-model` header.
 pub async fn google_ai_completion(
+    req: HttpRequest,
     body: Json<AiCompletionRequest>,
-) -> Result<String, Box<dyn Error>> {
-    let gemini_api_key = std::env::var("GEMINI_API_KEY").unwrap();
-    let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
-    let start_time = Instant::now();
-    headers.insert(
-        "Content-Type".parse::<HeaderName>().unwrap(),
-        "application/json".parse::<HeaderValue>().unwrap(),
-    );
-
-    let function = "generateContent";
+) -> Result<HttpResponse, Box<dyn Error>> {
     let model = body.model.clone().unwrap_or(GEMINI_MODEL_FLASH.to_string());
-
-    let google_ai_completion_response = match client
-        .post(format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:{}?key={}",
-            model, function, gemini_api_key
-        ))
-        .body(
-            serde_json::to_string(&json!({
-               "contents":[
-                {
-                    "parts":[
-                        {
-                            "text": body.query
-                        }
-                    ]
-                }
-               ]
-            }))
-            .unwrap(),
-        )
-        .headers(headers)
-        .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(e) => {
-            log_error(&format!("Request failed: {}", e));
-            return Ok(format!("Request failed: {}", e));
-        }
-    };
-
-    if !google_ai_completion_response.status().is_success() {
-        return Ok(format!(
-            "HTTP error! status: {}",
-            google_ai_completion_response.status()
-        ));
+    let model_access = model_access(&req);
+    if let Some(response) = SearchPipeline::model_unavailable(&model, None, &model_access) {
+        return Ok(response);
     }
 
-    let end_time = Instant::now();
-    let duration = end_time.duration_since(start_time);
-    println!(
-        "Google AI Completion using {} time taken: {:?}",
-        model, duration
-    );
-
-    let google_ai_completion_response_json: GoogleAiGenerateContentResponse =
-        match google_ai_completion_response
-            .json::<GoogleAiGenerateContentResponse>()
-            .await
-        {
-            Ok(response) => response,
-            Err(e) => {
-                log_error(&format!("Failed to parse JSON response: {}", e));
-                return Ok(format!("Failed to parse JSON response: {}", e));
+    match GoogleAi::completion(&body.query, &model, &model_access).await {
+        Ok(completion) => Ok(HttpResponse::Ok()
+            .insert_header(("x-googlexity-model", completion.model.as_str()))
+            .body(completion.text)),
+        Err(e) => match e.downcast_ref::<GoogleAiCompletionError>() {
+            Some(GoogleAiCompletionError::Http { .. }) | None => {
+                Ok(HttpResponse::Ok().body(e.to_string()))
             }
-        };
-
-    let content = &google_ai_completion_response_json.candidates[0]
-        .content
-        .parts[0]
-        .text;
-
-    Ok(content.to_string())
+            Some(_) => Err(e),
+        },
+    }
 }
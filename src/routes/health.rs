@@ -0,0 +1,103 @@
+use actix_web::{web::Query, HttpResponse};
+use futures::future::join_all;
+use std::time::{Duration, Instant};
+
+use crate::constants::config::{READINESS_PROBE_TIMEOUT_MS, REQUIRED_CONFIG};
+use crate::middleware::guard_middleware::AuthModes;
+use crate::models::health_models::{
+    Providers, Readiness, ReadinessQuery, UpstreamProbe, VersionInfo,
+};
+use crate::services::fixtures::Fixtures;
+use crate::services::jwt_auth::JwtAuth;
+use crate::services::page_rendering::PageRendering;
+use crate::services::site_extractors::SiteExtractor;
+use crate::services::upstreams::{Upstream, Upstreams};
+
+/// Liveness: the server is up and answering requests.
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness: the required config (including `API_KEY` or the JWT settings
+/// depending on `AUTH_MODES`) is set and, with `?probe=true`, the
+/// Custom Search and Gemini APIs are reachable. 503 when not ready.
+pub async fn readyz(query: Query<ReadinessQuery>) -> HttpResponse {
+    let auth_modes = AuthModes::from_env();
+    let mut missing_config: Vec<String> = REQUIRED_CONFIG
+        .iter()
+        .filter(|name| auth_modes.api_key || **name != "API_KEY")
+        .filter(|name| {
+            std::env::var(name)
+                .map(|value| value.trim().is_empty())
+                .unwrap_or(true)
+        })
+        .map(|name| name.to_string())
+        .collect();
+    if auth_modes.jwt && !JwtAuth::is_configured() {
+        missing_config.push("JWT_ISSUER".to_string());
+    }
+
+    let upstreams = if query.probe.unwrap_or(false) {
+        Some(join_all([Upstream::CustomSearch, Upstream::Gemini].map(probe)).await)
+    } else {
+        None
+    };
+
+    let ready = missing_config.is_empty()
+        && upstreams
+            .as_ref()
+            .is_none_or(|probes| probes.iter().all(|probe| probe.reachable));
+    let readiness = Readiness {
+        ready,
+        missing_config,
+        upstreams,
+    };
+
+    if ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+async fn probe(upstream: Upstream) -> UpstreamProbe {
+    let url = Upstreams::base_url(upstream);
+    let start_time = Instant::now();
+    let response = Upstreams::client(upstream)
+        .get(&url)
+        .timeout(Duration::from_millis(READINESS_PROBE_TIMEOUT_MS))
+        .send()
+        .await;
+
+    let (status, error) = match response {
+        Ok(response) => (Some(response.status().as_u16()), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    UpstreamProbe {
+        upstream: upstream.name().to_string(),
+        url,
+        reachable: status.is_some(),
+        status,
+        error,
+        duration_ms: start_time.elapsed().as_millis(),
+    }
+}
+
+/// Build info and the configured providers.
+pub async fn version() -> HttpResponse {
+    HttpResponse::Ok().json(VersionInfo {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: option_env!("GIT_COMMIT").map(|commit| commit.to_string()),
+        providers: Providers {
+            search: "google_custom_search".to_string(),
+            llm: "gemini".to_string(),
+            render_backend: PageRendering::backend().map(|backend| backend.name().to_string()),
+            site_extractors: SiteExtractor::enabled()
+                .iter()
+                .map(|extractor| extractor.name().to_string())
+                .collect(),
+            fixture_mode: Fixtures::mode().name().to_string(),
+        },
+    })
+}
@@ -0,0 +1,28 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::middleware::guard_middleware::constant_time_eq;
+use crate::services::metrics::Metrics;
+
+/// Prometheus metrics. Not behind `API_KEY`; set `METRICS_TOKEN` to require
+/// `Authorization: Bearer <METRICS_TOKEN>` instead.
+pub async fn metrics(req: HttpRequest) -> HttpResponse {
+    if let Ok(token) = std::env::var("METRICS_TOKEN") {
+        if !token.is_empty() {
+            let authorized = req
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|value| constant_time_eq(value.as_bytes(), token.as_bytes()));
+            if !authorized {
+                return HttpResponse::Unauthorized()
+                    .insert_header(("WWW-Authenticate", "Bearer"))
+                    .finish();
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(Metrics::render())
+}
@@ -1 +1,44 @@
+use actix_web::web;
+
+use crate::middleware::concurrency_middleware::ConcurrencyLimitMiddleware;
+
 pub mod googlexity;
+pub mod health;
+pub mod metrics;
+pub mod search_jobs;
+
+/// All routes, shared by the server and the integration tests.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/healthz", web::get().to(health::healthz))
+        .route("/readyz", web::get().to(health::readyz))
+        .route("/version", web::get().to(health::version))
+        .route("/metrics", web::get().to(metrics::metrics))
+        .service(web::scope("/api").configure(api_routes));
+}
+
+/// The routes under `/api`. Searches and summaries go through the
+/// concurrency limits.
+fn api_routes(r: &mut web::ServiceConfig) {
+    r.service(
+        web::resource("/search")
+            .wrap(ConcurrencyLimitMiddleware)
+            .route(web::post().to(googlexity::search)),
+    );
+    r.service(
+        web::resource("/summarize")
+            .wrap(ConcurrencyLimitMiddleware)
+            .route(web::post().to(googlexity::summarize)),
+    );
+    r.route("/search/jobs", web::post().to(search_jobs::create));
+    r.route("/search/jobs/{id}", web::get().to(search_jobs::status));
+    r.route("/search/raw", web::post().to(googlexity::search_raw));
+    r.route("/scrape", web::post().to(googlexity::scrape));
+    r.route(
+        "/prompt/preview",
+        web::post().to(googlexity::prompt_preview),
+    );
+    r.route(
+        "/generate-content",
+        web::post().to(googlexity::google_ai_completion),
+    );
+}
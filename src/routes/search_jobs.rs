@@ -0,0 +1,67 @@
+use actix_web::{
+    http::header::{LOCATION, RETRY_AFTER},
+    web::{Json, Path},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use serde_json::json;
+
+use crate::constants::config::{DEFAULT_RATE_LIMIT_TIER, SEARCH_RETRY_AFTER_SECS};
+use crate::models::auth_models::Principal;
+use crate::models::job_models::{SearchJobCreated, SearchJobRequest};
+use crate::routes::googlexity;
+use crate::services::domain_filtering::DomainFiltering;
+use crate::services::search_jobs::{JobCaller, SearchJobError, SearchJobs};
+
+/// Starts a search in the background and answers 202 with the job ID and its
+/// status URL (also in `Location`), without waiting for the search. The
+/// caller's domain policy and model access are resolved up front.
+pub async fn create(req: HttpRequest, body: Json<SearchJobRequest>) -> HttpResponse {
+    let (subject, tier) = match req.extensions().get::<Principal>() {
+        Some(principal) => (principal.subject.clone(), principal.tier.clone()),
+        None => ("anonymous".to_string(), DEFAULT_RATE_LIMIT_TIER.to_string()),
+    };
+    let caller = JobCaller {
+        subject,
+        tier,
+        domain_policy: DomainFiltering::policy_for_request(&req, &body.search),
+        model_access: googlexity::model_access(&req),
+    };
+    match SearchJobs::submit(body.into_inner(), caller) {
+        Ok(job) => {
+            let status_url = format!("/api/search/jobs/{}", job.id);
+            HttpResponse::Accepted()
+                .insert_header((LOCATION, status_url.as_str()))
+                .json(SearchJobCreated {
+                    id: job.id,
+                    status: job.status,
+                    status_url,
+                })
+        }
+        Err(e) => {
+            let body = json!({ "error": e.code(), "message": e.to_string() });
+            match e {
+                SearchJobError::QueueFull => HttpResponse::ServiceUnavailable()
+                    .insert_header((RETRY_AFTER, SEARCH_RETRY_AFTER_SECS.to_string()))
+                    .json(body),
+                _ => HttpResponse::BadRequest().json(body),
+            }
+        }
+    }
+}
+
+/// The job's status, finished stages and, once done, its result or error.
+/// Jobs created by other callers are reported as not found.
+pub async fn status(req: HttpRequest, id: Path<String>) -> HttpResponse {
+    match SearchJobs::get(&id, &caller(&req)) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound()
+            .json(json!({ "error": "not_found", "message": "No such search job" })),
+    }
+}
+
+fn caller(req: &HttpRequest) -> String {
+    req.extensions()
+        .get::<Principal>()
+        .map(|principal| principal.subject.clone())
+        .unwrap_or_else(|| "anonymous".to_string())
+}
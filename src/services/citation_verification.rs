@@ -0,0 +1,358 @@
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use url::Url;
+
+use crate::constants::config::VERIFICATION_SUPPORT_THRESHOLD;
+use crate::models::google_search_models::{CitationReport, ClaimSupport, SearchResult};
+
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was",
+    "one", "our", "out", "has", "have", "his", "how", "its", "may", "new", "now", "see", "who",
+    "did", "get", "let", "she", "too", "use", "with", "this", "that", "from", "they", "will",
+    "would", "there", "their", "what", "about", "which", "when", "were", "been", "than", "then",
+    "them", "these", "those", "also", "into", "more", "most", "such", "some", "only", "other",
+    "over", "very", "just", "each", "while", "where", "here", "your", "source", "sources",
+];
+
+/// Numbered citations like `[1](uri)`, one or more, at the start of the text
+static LEADING_CITATIONS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\s*\[\d+\]\([^)]*\))+").unwrap());
+static CITATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\d+\]\([^)]*\)").unwrap());
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap());
+static LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"https?://[^\s)\]>"'`]+"#).unwrap());
+static BARE_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"https?://\S+").unwrap());
+
+struct Passage<'a> {
+    link: &'a str,
+    text: String,
+    words: HashSet<String>,
+}
+
+pub struct CitationVerification;
+
+impl CitationVerification {
+    /// Splits the answer into claims and scores each one by how much of it is
+    /// covered by the best matching passage of the scraped content or snippets.
+    /// Links in the answer that aren't in `search_results` are reported too.
+    pub fn verify(answer: &str, search_results: &[SearchResult]) -> CitationReport {
+        let passages = Self::passages(search_results);
+        let result_links: HashSet<String> = search_results
+            .iter()
+            .map(|item| Self::normalize_link(&item.link))
+            .collect();
+
+        let claims: Vec<ClaimSupport> = Self::claims(answer)
+            .into_iter()
+            .map(|claim| Self::verify_claim(&claim, &passages))
+            .collect();
+
+        let mut unknown_sources: Vec<String> = Vec::new();
+        for link in Self::links(answer) {
+            if !result_links.contains(&Self::normalize_link(&link))
+                && !unknown_sources.contains(&link)
+            {
+                unknown_sources.push(link);
+            }
+        }
+
+        let unsupported_claims = claims.iter().filter(|claim| !claim.supported).count();
+        let support_score = if claims.is_empty() {
+            0.0
+        } else {
+            claims.iter().map(|claim| claim.score).sum::<f64>() / claims.len() as f64
+        };
+
+        CitationReport {
+            support_score,
+            unsupported_claims,
+            unknown_sources,
+            claims,
+        }
+    }
+
+    fn verify_claim(claim: &str, passages: &[Passage]) -> ClaimSupport {
+        let claim_text = Self::strip_links(claim);
+        let claim_words = Self::words(&claim_text);
+        let claim_numbers: Vec<&String> = claim_words
+            .iter()
+            .filter(|word| word.chars().any(|c| c.is_ascii_digit()))
+            .collect();
+
+        let mut best: Option<(f64, &Passage)> = None;
+        for passage in passages {
+            let overlap = claim_words.intersection(&passage.words).count();
+            let mut score = overlap as f64 / claim_words.len().max(1) as f64;
+
+            // Numbers are the facts most likely to be made up, so weigh them separately.
+            if !claim_numbers.is_empty() {
+                let found = claim_numbers
+                    .iter()
+                    .filter(|number| passage.words.contains(**number))
+                    .count();
+                score *= found as f64 / claim_numbers.len() as f64;
+            }
+
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, passage));
+            }
+        }
+
+        let (score, passage) = match best {
+            Some((score, passage)) if score > 0.0 => (score, Some(passage)),
+            _ => (0.0, None),
+        };
+
+        ClaimSupport {
+            claim: claim.to_string(),
+            score,
+            supported: score >= VERIFICATION_SUPPORT_THRESHOLD,
+            source: passage.map(|passage| passage.link.to_string()),
+            passage: passage.map(|passage| passage.text.clone()),
+            cited_sources: Self::links(claim),
+        }
+    }
+
+    /// Bullet points, table rows and sentences of the answer, skipping headings,
+    /// source lines and fragments too short to check.
+    fn claims(answer: &str) -> Vec<String> {
+        let mut claims: Vec<String> = Vec::new();
+
+        for line in answer.lines() {
+            let line = line
+                .trim()
+                .trim_start_matches(['-', '*', '#', '>', '|'])
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+                .trim();
+            if line.is_empty()
+                || line.to_lowercase().starts_with("source")
+                || line.chars().all(|c| matches!(c, '-' | '|' | ':' | ' '))
+            {
+                continue;
+            }
+
+            for sentence in Self::sentences(line) {
+                if Self::words(&Self::strip_links(sentence)).len() >= 3 {
+                    claims.push(sentence.to_string());
+                }
+            }
+        }
+
+        claims
+    }
+
+    fn passages(search_results: &[SearchResult]) -> Vec<Passage<'_>> {
+        let mut passages: Vec<Passage> = Vec::new();
+
+        for item in search_results {
+            passages.push(Passage {
+                link: &item.link,
+                text: item.snippet.clone(),
+                words: Self::words(&item.snippet),
+            });
+
+            if let Some(content) = &item.website_text_content {
+                let sentences = Self::sentences(content);
+                // Pairs of sentences so claims that span a sentence boundary still match.
+                for window in sentences.windows(sentences.len().clamp(1, 2)) {
+                    let text = window.join(" ");
+                    passages.push(Passage {
+                        link: &item.link,
+                        words: Self::words(&text),
+                        text,
+                    });
+                }
+            }
+        }
+
+        passages
+    }
+
+    /// Splits on `.`, `!` or `?` followed by whitespace, so dots inside links
+    /// and numbers don't end a sentence. Numbered citations right after the
+    /// end, like grounded answers have, stay with their sentence.
+    fn sentences(text: &str) -> Vec<&str> {
+        let mut sentences: Vec<&str> = Vec::new();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((index, c)) = chars.next() {
+            if index < start {
+                continue;
+            }
+            let ends_sentence = matches!(c, '.' | '!' | '?')
+                && chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+            if ends_sentence {
+                let end = index
+                    + 1
+                    + LEADING_CITATIONS
+                        .find(&text[index + 1..])
+                        .map_or(0, |citations| citations.end());
+                let sentence = text[start..end].trim();
+                if !sentence.is_empty() {
+                    sentences.push(sentence);
+                }
+                start = end;
+            }
+        }
+
+        let sentence = text[start..].trim();
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+
+        sentences
+    }
+
+    fn words(text: &str) -> HashSet<String> {
+        text.split(|c: char| !(c.is_alphanumeric() || c == '.' || c == ','))
+            .map(|word| {
+                word.trim_matches(|c: char| c == '.' || c == ',')
+                    .replace(',', "")
+                    .to_lowercase()
+            })
+            .filter(|word| {
+                (word.len() > 2 || word.chars().any(|c| c.is_ascii_digit()))
+                    && !STOP_WORDS.contains(&word.as_str())
+            })
+            .collect()
+    }
+
+    fn links(text: &str) -> Vec<String> {
+        let mut links: Vec<String> = Vec::new();
+        for link in LINK.find_iter(text) {
+            let link = link.as_str().trim_end_matches(['.', ',', ';']).to_string();
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+        links
+    }
+
+    /// Replaces markdown links with their text and removes bare URLs. Numbered
+    /// citations like `[1](uri)` are removed entirely, so the number isn't
+    /// checked as a fact.
+    fn strip_links(text: &str) -> String {
+        let without_citations = CITATION.replace_all(text, "");
+        let without_markdown_links = MARKDOWN_LINK.replace_all(&without_citations, "$1");
+        BARE_URL
+            .replace_all(&without_markdown_links, "")
+            .to_string()
+    }
+
+    fn normalize_link(link: &str) -> String {
+        match Url::parse(link) {
+            Ok(mut parsed_url) => {
+                parsed_url.set_fragment(None);
+                let host = parsed_url
+                    .host_str()
+                    .unwrap_or("")
+                    .trim_start_matches("www.");
+                format!("{}{}", host, parsed_url.path().trim_end_matches('/'))
+                    + &parsed_url
+                        .query()
+                        .map(|query| format!("?{}", query))
+                        .unwrap_or_default()
+            }
+            Err(_) => link.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(link: &str, snippet: &str, content: Option<&str>) -> SearchResult {
+        let mut result = SearchResult::from_link(link);
+        result.snippet = snippet.to_string();
+        result.website_text_content = content.map(|content| content.to_string());
+        result
+    }
+
+    fn eiffel_results() -> Vec<SearchResult> {
+        vec![
+            result(
+                "https://example.com/eiffel",
+                "The Eiffel Tower in Paris.",
+                Some("Construction began in 1887. The Eiffel Tower was completed in 1889 for the World's Fair."),
+            ),
+            result(
+                "https://example.org/louvre",
+                "The Louvre is the most visited museum in the world.",
+                None,
+            ),
+        ]
+    }
+
+    #[test]
+    fn supports_claims_found_in_the_scraped_content() {
+        let report = CitationVerification::verify(
+            "The Eiffel Tower was completed in 1889. [1](https://example.com/eiffel)",
+            &eiffel_results(),
+        );
+
+        assert_eq!(report.claims.len(), 1);
+        let claim = &report.claims[0];
+        assert_eq!(claim.score, 1.0);
+        assert!(claim.supported);
+        assert_eq!(claim.source.as_deref(), Some("https://example.com/eiffel"));
+        assert_eq!(claim.cited_sources, vec!["https://example.com/eiffel"]);
+        assert_eq!(report.unsupported_claims, 0);
+        assert_eq!(report.support_score, 1.0);
+    }
+
+    #[test]
+    fn claims_with_numbers_missing_from_the_sources_are_unsupported() {
+        let report = CitationVerification::verify(
+            "The Eiffel Tower was completed in 1925.",
+            &eiffel_results(),
+        );
+
+        assert_eq!(report.claims[0].score, 0.0);
+        assert!(!report.claims[0].supported);
+        assert_eq!(report.claims[0].source, None);
+        assert_eq!(report.unsupported_claims, 1);
+    }
+
+    #[test]
+    fn scores_the_answer_as_the_mean_of_its_claims() {
+        let report = CitationVerification::verify(
+            "- The Louvre is the most visited museum in the world.\n\
+             - The Louvre has a glass pyramid designed by Pei.",
+            &eiffel_results(),
+        );
+
+        assert_eq!(report.claims.len(), 2);
+        assert!(report.claims[0].supported);
+        assert!(!report.claims[1].supported);
+        let mean = (report.claims[0].score + report.claims[1].score) / 2.0;
+        assert!((report.support_score - mean).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn reports_links_that_are_not_search_results() {
+        let report = CitationVerification::verify(
+            "The Eiffel Tower was completed in 1889 (https://www.example.com/eiffel/#history). \
+             It is 330 metres tall (https://made-up.example/tower).",
+            &eiffel_results(),
+        );
+
+        assert_eq!(
+            report.unknown_sources,
+            vec!["https://made-up.example/tower"]
+        );
+    }
+
+    #[test]
+    fn skips_headings_source_lines_and_short_fragments() {
+        let report = CitationVerification::verify(
+            "# Eiffel\n\nSources:\n- https://example.com/eiffel\nYes indeed.\n|---|---|",
+            &eiffel_results(),
+        );
+
+        assert!(report.claims.is_empty());
+        assert_eq!(report.support_score, 0.0);
+    }
+}
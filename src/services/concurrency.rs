@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::constants::config::{
+    DEFAULT_SEARCH_MAX_CONCURRENCY, DEFAULT_SEARCH_MAX_CONCURRENCY_PER_KEY,
+    DEFAULT_SEARCH_QUEUE_SIZE, DEFAULT_SEARCH_QUEUE_TIMEOUT_MS,
+};
+use crate::constants::utility::log_error;
+
+/// A semaphore resized in place when its configured limit changes, so
+/// permits held across the change still count against the new limit.
+struct LimitSemaphore {
+    limit: usize,
+    /// Permits still to be taken away after the limit was lowered while they were held
+    excess: usize,
+    semaphore: Arc<Semaphore>,
+}
+
+impl LimitSemaphore {
+    fn new(limit: usize) -> Self {
+        LimitSemaphore {
+            limit,
+            excess: 0,
+            semaphore: Arc::new(Semaphore::new(limit)),
+        }
+    }
+
+    fn resize(&mut self, limit: usize) {
+        if limit > self.limit {
+            let added = limit - self.limit;
+            let cancelled = added.min(self.excess);
+            self.excess -= cancelled;
+            self.semaphore.add_permits(added - cancelled);
+        } else {
+            self.excess += self.limit - limit;
+        }
+        self.limit = limit;
+        self.excess -= self.semaphore.forget_permits(self.excess);
+    }
+
+    /// Nobody holds or waits for a permit, so the entry can be dropped.
+    fn is_idle(&self) -> bool {
+        Arc::strong_count(&self.semaphore) == 1 && self.semaphore.available_permits() >= self.limit
+    }
+}
+
+/// Semaphores by scope (`global` or `key:<subject>`).
+static SEMAPHORES: LazyLock<Mutex<HashMap<String, LimitSemaphore>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Searches currently waiting for a slot.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// Why a search wasn't let through, and which limit was exhausted (`global` or `key`).
+#[derive(Debug, Clone, Copy)]
+pub enum ConcurrencyRejection {
+    QueueFull { scope: &'static str },
+    Timeout { scope: &'static str },
+}
+
+impl ConcurrencyRejection {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::QueueFull { .. } => "queue_full",
+            Self::Timeout { .. } => "queue_timeout",
+        }
+    }
+
+    pub fn scope(&self) -> &'static str {
+        match self {
+            Self::QueueFull { scope } | Self::Timeout { scope } => scope,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self.scope() {
+            "key" => "Too many concurrent searches for this API key, try again later",
+            _ => "The server is busy, try again later",
+        }
+    }
+}
+
+/// Held for as long as the search runs.
+pub struct SearchPermit {
+    _key: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+/// A place in the wait queue, given up when dropped.
+struct QueueSlot;
+
+impl QueueSlot {
+    fn enter(queue_size: usize) -> Option<Self> {
+        QUEUED
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < queue_size).then_some(queued + 1)
+            })
+            .ok()
+            .map(|_| QueueSlot)
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        QUEUED.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Limits how many searches run at once, globally (`SEARCH_MAX_CONCURRENCY`) and
+/// per caller (`SEARCH_MAX_CONCURRENCY_PER_KEY`, or by rate-limit tier with
+/// `SEARCH_TIER_CONCURRENCY`). Searches over a limit wait in a queue of up to
+/// `SEARCH_QUEUE_SIZE` for at most `SEARCH_QUEUE_TIMEOUT_MS`; background jobs
+/// wait without either.
+pub struct ConcurrencyLimits;
+
+impl ConcurrencyLimits {
+    pub async fn acquire(subject: &str, tier: &str) -> Result<SearchPermit, ConcurrencyRejection> {
+        let (key_semaphore, global_semaphore) = Self::semaphores(subject, tier);
+
+        if let Ok(key_permit) = Arc::clone(&key_semaphore).try_acquire_owned() {
+            if let Ok(global_permit) = Arc::clone(&global_semaphore).try_acquire_owned() {
+                return Ok(SearchPermit {
+                    _key: key_permit,
+                    _global: global_permit,
+                });
+            }
+        }
+
+        // Whichever limit is exhausted, the caller's own or everyone's.
+        let scope = if key_semaphore.available_permits() == 0 {
+            "key"
+        } else {
+            "global"
+        };
+        let queue_size = Self::setting("SEARCH_QUEUE_SIZE", DEFAULT_SEARCH_QUEUE_SIZE as u64);
+        let _slot = QueueSlot::enter(queue_size as usize)
+            .ok_or(ConcurrencyRejection::QueueFull { scope })?;
+
+        let queue_timeout = Duration::from_millis(Self::setting(
+            "SEARCH_QUEUE_TIMEOUT_MS",
+            DEFAULT_SEARCH_QUEUE_TIMEOUT_MS,
+        ));
+        let permits =
+            tokio::time::timeout(queue_timeout, Self::wait(key_semaphore, global_semaphore)).await;
+
+        permits.map_err(|_| ConcurrencyRejection::Timeout { scope })
+    }
+
+    /// Waits under the same limits for as long as it takes, outside the wait
+    /// queue, for background jobs that have already been accepted.
+    pub async fn acquire_without_timeout(subject: &str, tier: &str) -> SearchPermit {
+        let (key_semaphore, global_semaphore) = Self::semaphores(subject, tier);
+        Self::wait(key_semaphore, global_semaphore).await
+    }
+
+    /// The caller's own slot first, so a burst from one caller doesn't hold
+    /// global slots while it waits.
+    async fn wait(key_semaphore: Arc<Semaphore>, global_semaphore: Arc<Semaphore>) -> SearchPermit {
+        // Acquiring only fails on a closed semaphore, and these are never closed.
+        let key_permit = key_semaphore
+            .acquire_owned()
+            .await
+            .expect("concurrency semaphores are never closed");
+        let global_permit = global_semaphore
+            .acquire_owned()
+            .await
+            .expect("concurrency semaphores are never closed");
+        SearchPermit {
+            _key: key_permit,
+            _global: global_permit,
+        }
+    }
+
+    /// The caller's and the global semaphore.
+    fn semaphores(subject: &str, tier: &str) -> (Arc<Semaphore>, Arc<Semaphore>) {
+        let key_semaphore = Self::semaphore(&format!("key:{}", subject), Self::key_limit(tier));
+        let global_semaphore = Self::semaphore(
+            "global",
+            Self::setting(
+                "SEARCH_MAX_CONCURRENCY",
+                DEFAULT_SEARCH_MAX_CONCURRENCY as u64,
+            ) as usize,
+        );
+        (key_semaphore, global_semaphore)
+    }
+
+    /// The semaphore for a scope, resized to its configured limit. Idle
+    /// semaphores are removed, so there's one per caller with searches running
+    /// rather than one per caller ever seen.
+    fn semaphore(scope: &str, limit: usize) -> Arc<Semaphore> {
+        let mut semaphores = SEMAPHORES.lock().unwrap_or_else(PoisonError::into_inner);
+        semaphores.retain(|_, existing| !existing.is_idle());
+        let entry = semaphores
+            .entry(scope.to_string())
+            .or_insert_with(|| LimitSemaphore::new(limit));
+        entry.resize(limit);
+        Arc::clone(&entry.semaphore)
+    }
+
+    /// The tier's limit from `SEARCH_TIER_CONCURRENCY` (a JSON object of tier to
+    /// limit, e.g. `{"premium": 8}`), else `SEARCH_MAX_CONCURRENCY_PER_KEY`.
+    fn key_limit(tier: &str) -> usize {
+        let tier_limit = std::env::var("SEARCH_TIER_CONCURRENCY")
+            .ok()
+            .filter(|tiers| !tiers.trim().is_empty())
+            .and_then(|tiers| {
+                serde_json::from_str::<HashMap<String, usize>>(&tiers)
+                    .map_err(|e| log_error(&format!("Invalid SEARCH_TIER_CONCURRENCY: {}", e)))
+                    .ok()
+            })
+            .and_then(|tiers| tiers.get(tier).copied());
+
+        tier_limit.unwrap_or(Self::setting(
+            "SEARCH_MAX_CONCURRENCY_PER_KEY",
+            DEFAULT_SEARCH_MAX_CONCURRENCY_PER_KEY as u64,
+        ) as usize)
+    }
+
+    fn setting(name: &str, default: u64) -> u64 {
+        match std::env::var(name) {
+            Ok(value) if !value.trim().is_empty() => value.trim().parse().unwrap_or_else(|_| {
+                log_error(&format!("Invalid {}: {}", name, value));
+                default
+            }),
+            _ => default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_tracked(scope: &str) -> bool {
+        SEMAPHORES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(scope)
+    }
+
+    #[test]
+    fn lowering_the_limit_counts_permits_already_held() {
+        let semaphore = ConcurrencyLimits::semaphore("test:lowered", 2);
+        let first = Arc::clone(&semaphore).try_acquire_owned().unwrap();
+        let second = Arc::clone(&semaphore).try_acquire_owned().unwrap();
+
+        let semaphore = ConcurrencyLimits::semaphore("test:lowered", 1);
+        drop(first);
+        // The returned permit is taken away on the next lookup
+        let semaphore_after = ConcurrencyLimits::semaphore("test:lowered", 1);
+        assert!(Arc::ptr_eq(&semaphore, &semaphore_after));
+        assert_eq!(semaphore.available_permits(), 0);
+
+        drop(second);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn raising_the_limit_adds_permits() {
+        let semaphore = ConcurrencyLimits::semaphore("test:raised", 1);
+        let _held = Arc::clone(&semaphore).try_acquire_owned().unwrap();
+
+        let semaphore_after = ConcurrencyLimits::semaphore("test:raised", 3);
+
+        assert!(Arc::ptr_eq(&semaphore, &semaphore_after));
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn idle_semaphores_are_removed() {
+        let semaphore = ConcurrencyLimits::semaphore("test:idle", 1);
+        let permit = Arc::clone(&semaphore).try_acquire_owned().unwrap();
+        drop(semaphore);
+
+        ConcurrencyLimits::semaphore("test:other", 1);
+        assert!(is_tracked("test:idle"));
+
+        drop(permit);
+        ConcurrencyLimits::semaphore("test:other", 1);
+        assert!(!is_tracked("test:idle"));
+    }
+}
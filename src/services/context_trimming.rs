@@ -0,0 +1,172 @@
+use crate::constants::config::{
+    CONTEXT_WINDOW_OUTPUT_RESERVE, DEFAULT_CONTEXT_WINDOW, LOCAL_TOKEN_ESTIMATE_MARGIN,
+};
+use crate::models::google_search_models::{ContextTrim, SearchResult};
+use crate::services::model_registry::ModelRegistry;
+use crate::services::pricing::Pricing;
+
+pub struct ContextTrimming;
+
+impl ContextTrimming {
+    /// Input token limit for a model from the model registry, less the space
+    /// reserved for the answer.
+    pub fn prompt_token_limit(model: &str) -> u64 {
+        let context_window = ModelRegistry::get(model)
+            .map(|definition| definition.context_window)
+            .unwrap_or(DEFAULT_CONTEXT_WINDOW);
+
+        context_window.saturating_sub(CONTEXT_WINDOW_OUTPUT_RESERVE)
+    }
+
+    /// Whether the local token estimate leaves enough headroom under
+    /// `token_limit` to skip counting the prompt's tokens remotely.
+    pub fn clearly_fits(prompt: &str, token_limit: u64) -> bool {
+        Pricing::estimate_tokens(prompt).saturating_mul(LOCAL_TOKEN_ESTIMATE_MARGIN) <= token_limit
+    }
+
+    /// Drops the lowest-ranked scraped content, then the lowest-ranked results
+    /// entirely, until the estimated prompt fits within `token_limit`.
+    ///
+    /// `measured_tokens` is the real token count of `prompt_prefix` plus the
+    /// serialised `search_results`; it calibrates the characters-per-token ratio
+    /// used for the estimates so only one count is needed per pass.
+    pub fn trim(
+        prompt_prefix: &str,
+        search_results: &mut Vec<SearchResult>,
+        measured_tokens: u64,
+        token_limit: u64,
+        trim: &mut ContextTrim,
+    ) {
+        let prompt_length = prompt_prefix.len() + Self::results_length(search_results);
+        let tokens_per_byte = measured_tokens as f64 / prompt_length.max(1) as f64;
+        let estimate = |length: usize| -> u64 { (length as f64 * tokens_per_byte).ceil() as u64 };
+
+        let mut estimated_length = prompt_length;
+
+        for item in search_results.iter_mut().rev() {
+            if estimate(estimated_length) <= token_limit {
+                return;
+            }
+            // Measured as serialised, so the field name and escaping count too.
+            let length = Self::result_length(item);
+            if item.website_text_content.take().is_some() {
+                estimated_length =
+                    estimated_length.saturating_sub(length - Self::result_length(item));
+                trim.dropped_website_content.push(item.link.clone());
+            }
+        }
+
+        while estimate(estimated_length) > token_limit {
+            let item = match search_results.pop() {
+                Some(item) => item,
+                None => return,
+            };
+            estimated_length = estimated_length.saturating_sub(Self::result_length(&item));
+            trim.dropped_results.push(item.link);
+        }
+    }
+
+    fn result_length(search_result: &SearchResult) -> usize {
+        serde_json::to_string(search_result)
+            .map(|result| result.len())
+            .unwrap_or(0)
+    }
+
+    fn results_length(search_results: &[SearchResult]) -> usize {
+        serde_json::to_string(search_results)
+            .map(|results| results.len())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFIX: &str = "Answer the query from these results:";
+
+    fn results(content_lengths: &[usize]) -> Vec<SearchResult> {
+        content_lengths
+            .iter()
+            .enumerate()
+            .map(|(i, length)| {
+                let mut result = SearchResult::from_link(&format!("https://example{}.com", i + 1));
+                result.website_text_content = Some("a".repeat(*length));
+                result
+            })
+            .collect()
+    }
+
+    /// Tokens at one per 4 bytes, like the measured counts `trim` is given.
+    fn tokens(search_results: &[SearchResult]) -> u64 {
+        ((PREFIX.len() + ContextTrimming::results_length(search_results)) / 4) as u64
+    }
+
+    #[test]
+    fn leaves_results_that_fit_untouched() {
+        let mut search_results = results(&[400, 400]);
+        let mut trim = ContextTrim::default();
+        let measured = tokens(&search_results);
+
+        ContextTrimming::trim(PREFIX, &mut search_results, measured, measured, &mut trim);
+
+        assert_eq!(search_results.len(), 2);
+        assert!(trim.dropped_website_content.is_empty());
+        assert!(trim.dropped_results.is_empty());
+    }
+
+    #[test]
+    fn drops_the_lowest_ranked_content_first() {
+        let mut search_results = results(&[4_000, 4_000, 4_000]);
+        let mut trim = ContextTrim::default();
+        let measured = tokens(&search_results);
+
+        ContextTrimming::trim(
+            PREFIX,
+            &mut search_results,
+            measured,
+            measured - 500,
+            &mut trim,
+        );
+
+        assert_eq!(trim.dropped_website_content, vec!["https://example3.com"]);
+        assert!(trim.dropped_results.is_empty());
+        assert_eq!(search_results.len(), 3);
+        assert!(search_results[1].website_text_content.is_some());
+        assert!(search_results[2].website_text_content.is_none());
+        assert!(tokens(&search_results) <= measured - 500);
+    }
+
+    #[test]
+    fn drops_whole_results_once_all_content_is_gone() {
+        let mut search_results = results(&[4_000, 4_000, 4_000]);
+        let mut trim = ContextTrim::default();
+        let measured = tokens(&search_results);
+        let limit = tokens(&results(&[0])) + 10;
+
+        ContextTrimming::trim(PREFIX, &mut search_results, measured, limit, &mut trim);
+
+        assert_eq!(
+            trim.dropped_website_content,
+            vec![
+                "https://example3.com",
+                "https://example2.com",
+                "https://example1.com"
+            ]
+        );
+        assert_eq!(
+            trim.dropped_results,
+            vec!["https://example3.com", "https://example2.com"]
+        );
+        assert_eq!(search_results.len(), 1);
+        assert_eq!(search_results[0].link, "https://example1.com");
+    }
+
+    #[test]
+    fn only_prompts_far_under_the_limit_clearly_fit() {
+        let prompt = "a".repeat(4_000);
+
+        assert!(ContextTrimming::clearly_fits(&prompt, 4_000));
+        assert!(!ContextTrimming::clearly_fits(&prompt, 3_999));
+    }
+}
@@ -0,0 +1,389 @@
+use actix_web::{HttpMessage, HttpRequest};
+use std::collections::HashMap;
+use url::Url;
+
+use crate::constants::config::{CREDIBILITY_HINT_THRESHOLD, DEFAULT_DOMAIN_REPUTATION};
+use crate::constants::utility::log_error;
+use crate::models::auth_models::Principal;
+use crate::models::google_search_models::{
+    DomainPolicies, DomainPolicy, SearchRequest, SearchResult,
+};
+
+pub struct DomainFiltering;
+
+impl DomainFiltering {
+    /// Combines the request's `include_domains`/`exclude_domains` with the policy
+    /// configured for the authenticated caller in the `DOMAIN_POLICIES` env var,
+    /// by its subject or else by `tier:<tier>`.
+    pub fn policy_for_request(req: &HttpRequest, body: &SearchRequest) -> DomainPolicy {
+        let key_policy = req
+            .extensions()
+            .get::<Principal>()
+            .and_then(|principal| {
+                let mut policies = Self::key_policies();
+                policies
+                    .remove(&principal.subject)
+                    .or_else(|| policies.remove(&format!("tier:{}", principal.tier)))
+            })
+            .unwrap_or_default();
+
+        let mut exclude_domains = key_policy.exclude_domains;
+        exclude_domains.extend(body.exclude_domains.clone().unwrap_or_default());
+
+        DomainPolicy {
+            include_domains: body.include_domains.clone().unwrap_or_default(),
+            key_include_domains: key_policy.include_domains,
+            exclude_domains,
+        }
+    }
+
+    fn key_policies() -> DomainPolicies {
+        match std::env::var("DOMAIN_POLICIES") {
+            Ok(policies) => serde_json::from_str(&policies).unwrap_or_else(|e| {
+                log_error(&format!("Invalid DOMAIN_POLICIES: {}", e));
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    pub fn host(url: &str) -> Option<String> {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+    }
+
+    /// `example.com` matches the domain and all of its subdomains, while patterns
+    /// with `*` are matched as globs against the whole host (`*.example.com`, `*.gov`).
+    pub fn matches(host: &str, pattern: &str) -> bool {
+        let pattern = pattern.trim().trim_end_matches('.').to_lowercase();
+        let pattern = match Url::parse(&pattern) {
+            Ok(url) if url.host_str().is_some() => url.host_str().unwrap_or_default().to_string(),
+            _ => pattern,
+        };
+
+        if !pattern.contains('*') {
+            return host == pattern || host.ends_with(&format!(".{}", pattern));
+        }
+
+        let regex_pattern = format!(
+            "^{}$",
+            pattern
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".*")
+        );
+        regex::Regex::new(&regex_pattern)
+            .map(|regex| regex.is_match(host))
+            .unwrap_or(false)
+    }
+
+    pub fn matches_any(host: &str, patterns: &[String]) -> bool {
+        patterns.iter().any(|pattern| Self::matches(host, pattern))
+    }
+
+    pub fn is_allowed(link: &str, policy: &DomainPolicy) -> bool {
+        let host = match Self::host(link) {
+            Some(host) => host,
+            None => return false,
+        };
+
+        (policy.include_domains.is_empty() || Self::matches_any(&host, &policy.include_domains))
+            && (policy.key_include_domains.is_empty()
+                || Self::matches_any(&host, &policy.key_include_domains))
+            && !Self::matches_any(&host, &policy.exclude_domains)
+    }
+
+    pub fn filter(search_results: Vec<SearchResult>, policy: &DomainPolicy) -> Vec<SearchResult> {
+        search_results
+            .into_iter()
+            .filter(|item| {
+                let allowed = Self::is_allowed(&item.link, policy);
+                if !allowed {
+                    tracing::info!(
+                        "URL {} is excluded by the domain policy, skipping",
+                        item.link
+                    );
+                }
+                allowed
+            })
+            .collect()
+    }
+
+    /// Custom Search parameters narrowing the search to the policy's domains:
+    /// `siteSearch` when there's a single domain to include or exclude, and
+    /// `site:`/`-site:` query operators otherwise. Results are filtered again
+    /// afterwards, as glob patterns can only be approximated here.
+    pub fn site_search_params(query: &str, policy: &DomainPolicy) -> Vec<(String, String)> {
+        let include_domains: Vec<String> = if policy.include_domains.is_empty() {
+            &policy.key_include_domains
+        } else {
+            &policy.include_domains
+        }
+        .iter()
+        .filter_map(|pattern| Self::site_operand(pattern))
+        .collect();
+        let exclude_domains: Vec<String> = policy
+            .exclude_domains
+            .iter()
+            .filter_map(|pattern| Self::site_operand(pattern))
+            .collect();
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        let mut query = query.to_string();
+
+        match (include_domains.as_slice(), exclude_domains.as_slice()) {
+            ([include_domain], _) => {
+                params.push(("siteSearch".to_string(), include_domain.clone()));
+                params.push(("siteSearchFilter".to_string(), "i".to_string()));
+                for exclude_domain in &exclude_domains {
+                    query.push_str(&format!(" -site:{}", exclude_domain));
+                }
+            }
+            ([], [exclude_domain]) => {
+                params.push(("siteSearch".to_string(), exclude_domain.clone()));
+                params.push(("siteSearchFilter".to_string(), "e".to_string()));
+            }
+            _ => {
+                if !include_domains.is_empty() {
+                    let sites: Vec<String> = include_domains
+                        .iter()
+                        .map(|domain| format!("site:{}", domain))
+                        .collect();
+                    query.push_str(&format!(" ({})", sites.join(" OR ")));
+                }
+                for exclude_domain in &exclude_domains {
+                    query.push_str(&format!(" -site:{}", exclude_domain));
+                }
+            }
+        }
+
+        params.insert(0, ("q".to_string(), query));
+        params
+    }
+
+    /// The domain Custom Search can filter on for a pattern: `*.example.com`
+    /// becomes `example.com`; other globs can't be expressed and are skipped.
+    fn site_operand(pattern: &str) -> Option<String> {
+        let domain = pattern.trim().trim_start_matches("*.").to_lowercase();
+        if domain.is_empty() || domain.contains('*') {
+            return None;
+        }
+        Some(domain)
+    }
+
+    /// The reputation score of a host from -1.0 to 1.0, taken from the most
+    /// specific matching entry of `DEFAULT_DOMAIN_REPUTATION` or the
+    /// `DOMAIN_REPUTATION` env var (JSON object of pattern to score).
+    pub fn reputation(host: &str) -> f64 {
+        let mut reputation: HashMap<String, f64> = DEFAULT_DOMAIN_REPUTATION
+            .iter()
+            .map(|(pattern, score)| (pattern.to_string(), *score))
+            .collect();
+
+        if let Ok(overrides) = std::env::var("DOMAIN_REPUTATION") {
+            match serde_json::from_str::<HashMap<String, f64>>(&overrides) {
+                Ok(overrides) => reputation.extend(overrides),
+                Err(e) => log_error(&format!("Invalid DOMAIN_REPUTATION: {}", e)),
+            }
+        }
+
+        reputation
+            .into_iter()
+            .filter(|(pattern, _)| Self::matches(host, pattern))
+            .max_by_key(|(pattern, _)| pattern.trim_start_matches("*.").len())
+            .map(|(_, score)| score.clamp(-1.0, 1.0))
+            .unwrap_or(0.0)
+    }
+
+    /// Re-ranks results by their search position adjusted by domain reputation,
+    /// and sets the `credibility` hint passed to Gemini for notable sources.
+    pub fn rank(search_results: Vec<SearchResult>) -> Vec<SearchResult> {
+        let result_count = search_results.len().max(1) as f64;
+        let mut scored: Vec<(f64, SearchResult)> = search_results
+            .into_iter()
+            .enumerate()
+            .map(|(position, mut item)| {
+                let reputation = Self::host(&item.link)
+                    .map(|host| Self::reputation(&host))
+                    .unwrap_or(0.0);
+                item.credibility = if reputation >= CREDIBILITY_HINT_THRESHOLD {
+                    Some("high".to_string())
+                } else if reputation <= -CREDIBILITY_HINT_THRESHOLD {
+                    Some("low".to_string())
+                } else {
+                    None
+                };
+                (1.0 - position as f64 / result_count + reputation, item)
+            })
+            .collect();
+
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        scored.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(include: &[&str], key_include: &[&str], exclude: &[&str]) -> DomainPolicy {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        DomainPolicy {
+            include_domains: patterns(include),
+            key_include_domains: patterns(key_include),
+            exclude_domains: patterns(exclude),
+        }
+    }
+
+    fn query_params(params: &[(String, String)]) -> Vec<(&str, &str)> {
+        params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn plain_patterns_match_the_domain_and_its_subdomains() {
+        assert!(DomainFiltering::matches("example.com", "example.com"));
+        assert!(DomainFiltering::matches("www.example.com", "Example.com."));
+        assert!(DomainFiltering::matches(
+            "www.example.com",
+            "https://example.com/page"
+        ));
+        assert!(!DomainFiltering::matches("badexample.com", "example.com"));
+        assert!(!DomainFiltering::matches(
+            "example.com.evil.net",
+            "example.com"
+        ));
+    }
+
+    #[test]
+    fn glob_patterns_match_the_whole_host() {
+        assert!(DomainFiltering::matches("data.gov", "*.gov"));
+        assert!(DomainFiltering::matches("www.data.gov", "*.gov"));
+        assert!(!DomainFiltering::matches("data.gov.uk", "*.gov"));
+        assert!(DomainFiltering::matches("docs.example.com", "docs.*.com"));
+        assert!(!DomainFiltering::matches("example.com", "*.example.com"));
+    }
+
+    #[test]
+    fn links_must_match_every_include_list_and_no_exclude() {
+        let policy = policy(
+            &["*.gov", "example.com"],
+            &["example.com"],
+            &["blog.example.com"],
+        );
+
+        assert!(DomainFiltering::is_allowed(
+            "https://www.example.com/a",
+            &policy
+        ));
+        assert!(!DomainFiltering::is_allowed("https://data.gov/a", &policy));
+        assert!(!DomainFiltering::is_allowed(
+            "https://blog.example.com/a",
+            &policy
+        ));
+        assert!(!DomainFiltering::is_allowed("not a url", &policy));
+        assert!(DomainFiltering::is_allowed(
+            "https://anything.net",
+            &DomainPolicy::default()
+        ));
+    }
+
+    #[test]
+    fn a_single_included_domain_uses_site_search() {
+        let params = DomainFiltering::site_search_params(
+            "rust",
+            &policy(&["*.example.com"], &[], &["a.com"]),
+        );
+
+        assert_eq!(
+            query_params(&params),
+            vec![
+                ("q", "rust -site:a.com"),
+                ("siteSearch", "example.com"),
+                ("siteSearchFilter", "i"),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_single_excluded_domain_uses_site_search() {
+        let params = DomainFiltering::site_search_params("rust", &policy(&[], &[], &["a.com"]));
+
+        assert_eq!(
+            query_params(&params),
+            vec![
+                ("q", "rust"),
+                ("siteSearch", "a.com"),
+                ("siteSearchFilter", "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn several_domains_use_site_operators() {
+        let params = DomainFiltering::site_search_params(
+            "rust",
+            &policy(
+                &["a.com", "b.org", "ex*ample.com"],
+                &["ignored.com"],
+                &["c.net", "d.net"],
+            ),
+        );
+
+        assert_eq!(
+            query_params(&params),
+            vec![(
+                "q",
+                "rust (site:a.com OR site:b.org) -site:c.net -site:d.net"
+            )]
+        );
+    }
+
+    #[test]
+    fn key_includes_are_used_when_the_request_has_none() {
+        let params = DomainFiltering::site_search_params("rust", &policy(&[], &["a.com"], &[]));
+
+        assert_eq!(
+            query_params(&params),
+            vec![
+                ("q", "rust"),
+                ("siteSearch", "a.com"),
+                ("siteSearchFilter", "i")
+            ]
+        );
+    }
+
+    #[test]
+    fn reputation_comes_from_the_most_specific_pattern() {
+        assert_eq!(DomainFiltering::reputation("www.sec.gov"), 0.4);
+        assert_eq!(DomainFiltering::reputation("data.gov"), 0.3);
+        assert_eq!(DomainFiltering::reputation("pinterest.com"), -0.4);
+        assert_eq!(DomainFiltering::reputation("unknown.example"), 0.0);
+    }
+
+    #[test]
+    fn ranks_by_position_and_reputation_with_credibility_hints() {
+        let ranked = DomainFiltering::rank(vec![
+            SearchResult::from_link("https://www.pinterest.com/pin/1"),
+            SearchResult::from_link("https://blog.example.com/post"),
+            SearchResult::from_link("https://www.sec.gov/filing"),
+        ]);
+
+        let ranked: Vec<(&str, Option<&str>)> = ranked
+            .iter()
+            .map(|item| (item.link.as_str(), item.credibility.as_deref()))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("https://www.sec.gov/filing", Some("high")),
+                ("https://blog.example.com/post", None),
+                ("https://www.pinterest.com/pin/1", Some("low")),
+            ]
+        );
+    }
+}
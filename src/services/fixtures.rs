@@ -0,0 +1,242 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf};
+use url::Url;
+
+use crate::constants::config::DEFAULT_FIXTURE_DIR;
+use crate::constants::utility::log_error;
+use crate::services::metrics::Metrics;
+use crate::services::web_scraping::WebScraping;
+
+/// Set with the `FIXTURE_MODE` env variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixtureMode {
+    /// Talk to the upstreams as usual.
+    Live,
+    /// Talk to the upstreams and save every response to `FIXTURE_DIR`.
+    Record,
+    /// Answer from `FIXTURE_DIR` without any network access.
+    Replay,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FixtureUpstream {
+    GoogleSearch,
+    Gemini,
+    Pages,
+}
+
+impl FixtureMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Live => "live",
+            Self::Record => "record",
+            Self::Replay => "replay",
+        }
+    }
+}
+
+impl FixtureUpstream {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::GoogleSearch => "google_search",
+            Self::Gemini => "gemini",
+            Self::Pages => "pages",
+        }
+    }
+}
+
+/// A recorded response, stored as `FIXTURE_DIR/<upstream>/<request hash>.json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    /// The request URL without API keys
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_body: Option<String>,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// The body when it isn't valid UTF-8
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+/// Record/replay for upstream requests, so tests, demos and CI can run
+/// deterministically without network access.
+pub struct Fixtures;
+
+impl Fixtures {
+    pub fn mode() -> FixtureMode {
+        match std::env::var("FIXTURE_MODE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "record" => FixtureMode::Record,
+            "replay" => FixtureMode::Replay,
+            _ => FixtureMode::Live,
+        }
+    }
+
+    /// Sends the request, recording or replaying its response depending on `FIXTURE_MODE`.
+    /// Replaying a request that was never recorded returns a 404.
+    pub async fn send(
+        upstream: FixtureUpstream,
+        request: RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        let mode = Self::mode();
+        if mode == FixtureMode::Live {
+            return request.send().await;
+        }
+
+        let (client, request) = request.build_split();
+        let request = request?;
+        let method = request.method().to_string();
+        let url = Self::redacted_url(request.url());
+        let request_body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| String::from_utf8_lossy(body).to_string());
+        let path = Self::path(upstream, &method, &url, request_body.as_deref());
+
+        if mode == FixtureMode::Replay {
+            return Ok(Self::replay(&path, &url));
+        }
+
+        let mut response = client.execute(request).await?;
+        // Pages are read no further than the scraper would, and pages it would
+        // skip as too large are passed on without being recorded.
+        let max_body_bytes = match upstream {
+            FixtureUpstream::Pages => WebScraping::max_body_bytes(),
+            _ => usize::MAX,
+        };
+        if response
+            .content_length()
+            .is_some_and(|content_length| content_length > max_body_bytes as u64)
+        {
+            return Ok(response);
+        }
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(|content_type| content_type.to_string());
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            let remaining = max_body_bytes.saturating_sub(bytes.len());
+            bytes.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+            if bytes.len() >= max_body_bytes {
+                break;
+            }
+        }
+
+        let (body, body_base64) = match String::from_utf8(bytes.clone()) {
+            Ok(body) => (Some(body), None),
+            Err(_) => (None, Some(STANDARD.encode(&bytes))),
+        };
+        let fixture = Fixture {
+            method,
+            url,
+            request_body,
+            status,
+            content_type: content_type.clone(),
+            body,
+            body_base64,
+        };
+        if let Err(e) = Self::save(&path, &fixture) {
+            log_error(&format!("Failed to save fixture {}: {}", path.display(), e));
+        }
+
+        Ok(Self::response(status, content_type.as_deref(), bytes))
+    }
+
+    fn replay(path: &PathBuf, url: &str) -> Response {
+        let fixture = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Fixture>(&contents).ok());
+
+        Metrics::record_cache_lookup("fixtures", fixture.is_some());
+        match fixture {
+            Some(fixture) => {
+                let bytes = match (fixture.body, fixture.body_base64) {
+                    (Some(body), _) => body.into_bytes(),
+                    (None, Some(body_base64)) => STANDARD.decode(body_base64).unwrap_or_default(),
+                    (None, None) => Vec::new(),
+                };
+                Self::response(fixture.status, fixture.content_type.as_deref(), bytes)
+            }
+            None => {
+                log_error(&format!(
+                    "No fixture recorded for {} ({})",
+                    url,
+                    path.display()
+                ));
+                Self::response(
+                    StatusCode::NOT_FOUND.as_u16(),
+                    Some("application/json"),
+                    serde_json::json!({ "error": "No fixture recorded", "url": url })
+                        .to_string()
+                        .into_bytes(),
+                )
+            }
+        }
+    }
+
+    fn save(path: &PathBuf, fixture: &Fixture) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(fixture)?)?;
+        Ok(())
+    }
+
+    fn response(status: u16, content_type: Option<&str>, body: Vec<u8>) -> Response {
+        let mut response = http::Response::builder().status(status);
+        if let Some(content_type) = content_type {
+            response = response.header(CONTENT_TYPE, content_type);
+        }
+        Response::from(response.body(body).unwrap_or_default())
+    }
+
+    fn path(
+        upstream: FixtureUpstream,
+        method: &str,
+        url: &str,
+        request_body: Option<&str>,
+    ) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(method.as_bytes());
+        hasher.update(b" ");
+        hasher.update(url.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(request_body.unwrap_or_default().as_bytes());
+
+        PathBuf::from(std::env::var("FIXTURE_DIR").unwrap_or(DEFAULT_FIXTURE_DIR.to_string()))
+            .join(upstream.name())
+            .join(format!("{:x}.json", hasher.finalize()))
+    }
+
+    /// The URL without API keys, so fixtures can be shared and replayed with other keys.
+    fn redacted_url(url: &Url) -> String {
+        let mut url = url.clone();
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| name != "key" && name != "access_token")
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        url.to_string()
+    }
+}
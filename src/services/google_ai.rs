@@ -0,0 +1,350 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::json;
+use std::{error::Error, time::Instant};
+
+use crate::constants::utility::{log_error, log_event, log_query};
+use crate::models::google_ai_models::{
+    AiCompletion, CountTokensResponse, GoogleAiCompletionError, GoogleAiGenerateContentResponse,
+};
+use crate::services::fixtures::{FixtureUpstream, Fixtures};
+use crate::services::grounding::Grounding;
+use crate::services::metrics::Metrics;
+use crate::services::model_registry::{ModelAccess, ModelCapability, ModelRegistry};
+use crate::services::pricing::Pricing;
+use crate::services::upstreams::{Upstream, Upstreams};
+
+/// Gemini `generateContent` and `countTokens` calls, with model fallbacks from
+/// the model registry.
+pub struct GoogleAi;
+
+impl GoogleAi {
+    pub async fn completion(
+        query: &str,
+        model: &str,
+        access: &ModelAccess,
+    ) -> Result<AiCompletion, Box<dyn Error>> {
+        let (answered_model, google_ai_completion_response_json) =
+            Self::generate_content_with_fallback(model, None, access, |_| {
+                json!({
+                   "contents":[
+                    {
+                        "parts":[
+                            {
+                                "text": query
+                            }
+                        ]
+                    }
+                   ]
+                })
+            })
+            .await?;
+
+        let content = match google_ai_completion_response_json.text() {
+            Ok(content) => content,
+            Err(e) => {
+                log_error(&format!(
+                    "Google AI Completion using {} failed: {}",
+                    answered_model, e
+                ));
+                return Err(e.into());
+            }
+        };
+
+        Ok(AiCompletion {
+            text: content,
+            model: answered_model,
+            usage_metadata: google_ai_completion_response_json.usage_metadata,
+        })
+    }
+
+    /// Like [`Self::completion`] but asks for a JSON response
+    /// (`responseMimeType: application/json`), only using models that support it.
+    pub async fn json_completion(
+        query: &str,
+        model: &str,
+        access: &ModelAccess,
+    ) -> Result<AiCompletion, Box<dyn Error>> {
+        let (answered_model, google_ai_completion_response_json) =
+            Self::generate_content_with_fallback(
+                model,
+                Some(ModelCapability::JsonMode),
+                access,
+                |_| {
+                    json!({
+                       "contents":[
+                        {
+                            "parts":[
+                                {
+                                    "text": query
+                                }
+                            ]
+                        }
+                       ],
+                       "generationConfig": {
+                           "responseMimeType": "application/json"
+                       }
+                    })
+                },
+            )
+            .await?;
+
+        let content = match google_ai_completion_response_json.text() {
+            Ok(content) => content,
+            Err(e) => {
+                log_error(&format!(
+                    "Google AI JSON Completion using {} failed: {}",
+                    answered_model, e
+                ));
+                return Err(e.into());
+            }
+        };
+
+        Ok(AiCompletion {
+            text: content,
+            model: answered_model,
+            usage_metadata: google_ai_completion_response_json.usage_metadata,
+        })
+    }
+
+    /// Answers the query with Gemini's built-in Google Search tool instead of the
+    /// Custom Search + scraping pipeline, returning the answer with citations appended.
+    pub async fn grounded_completion(
+        query: &str,
+        model: &str,
+        access: &ModelAccess,
+    ) -> Result<AiCompletion, Box<dyn Error>> {
+        let (answered_model, google_ai_completion_response_json) =
+            Self::generate_content_with_fallback(
+                model,
+                Some(ModelCapability::Tools),
+                access,
+                |model| {
+                    // Gemini 1.5 models only support the legacy retrieval tool.
+                    let tool = if model.starts_with("gemini-1.5") {
+                        json!({ "google_search_retrieval": {} })
+                    } else {
+                        json!({ "google_search": {} })
+                    };
+
+                    json!({
+                       "contents":[
+                        {
+                            "parts":[
+                                {
+                                    "text": query
+                                }
+                            ]
+                        }
+                       ],
+                       "tools": [tool]
+                    })
+                },
+            )
+            .await?;
+
+        let content = match google_ai_completion_response_json.text() {
+            Ok(content) => content,
+            Err(e) => {
+                log_error(&format!(
+                    "Google AI Grounded Completion using {} failed: {}",
+                    answered_model, e
+                ));
+                return Err(e.into());
+            }
+        };
+
+        let grounding_metadata = google_ai_completion_response_json
+            .candidates
+            .first()
+            .and_then(|candidate| candidate.grounding_metadata.as_ref());
+
+        if let Some(grounding_metadata) = grounding_metadata {
+            log_query(&format!(
+                "Grounded web search queries: {:?}",
+                grounding_metadata.web_search_queries
+            ));
+        }
+
+        Ok(AiCompletion {
+            text: Grounding::cite(&content, grounding_metadata),
+            model: answered_model,
+            usage_metadata: google_ai_completion_response_json.usage_metadata,
+        })
+    }
+
+    /// Counts prompt tokens with Gemini `countTokens`, falling back to a local estimate.
+    pub async fn count_tokens(model: &str, text: &str) -> u64 {
+        let gemini_api_key = std::env::var("GEMINI_API_KEY").unwrap_or_default();
+        let client = Upstreams::client(Upstream::Gemini);
+
+        let count_tokens_response = Fixtures::send(
+            FixtureUpstream::Gemini,
+            client
+                .post(Upstreams::gemini_url(model, "countTokens"))
+                .query(&[("key", &gemini_api_key)])
+                .json(&json!({
+                    "contents": [{ "parts": [{ "text": text }] }]
+                })),
+        )
+        .await;
+
+        let count_tokens_response = match count_tokens_response {
+            Ok(response) if response.status().is_success() => {
+                Metrics::record_gemini_request(model, "countTokens", "success");
+                response.json::<CountTokensResponse>().await.ok()
+            }
+            Ok(response) => {
+                Metrics::record_gemini_request(model, "countTokens", response.status().as_str());
+                log_error(&format!(
+                    "countTokens using {} failed with status: {}",
+                    model,
+                    response.status()
+                ));
+                None
+            }
+            Err(e) => {
+                Metrics::record_gemini_request(model, "countTokens", "error");
+                log_error(&format!("countTokens request failed: {}", e));
+                None
+            }
+        };
+
+        match count_tokens_response {
+            Some(response) => response.total_tokens,
+            None => Pricing::estimate_tokens(text),
+        }
+    }
+
+    /// Calls `generateContent` on the requested model, moving down its fallback
+    /// chain from the model registry when a model is deprecated, not allowed,
+    /// overloaded or gone. Returns the model that answered with its response.
+    async fn generate_content_with_fallback(
+        model: &str,
+        capability: Option<ModelCapability>,
+        access: &ModelAccess,
+        request_body: impl Fn(&str) -> serde_json::Value,
+    ) -> Result<(String, GoogleAiGenerateContentResponse), Box<dyn Error>> {
+        let fallback_chain = ModelRegistry::fallback_chain(model, capability, access);
+        let mut last_error: Box<dyn Error> = Box::new(GoogleAiCompletionError::NoAvailableModel {
+            requested: model.to_string(),
+        });
+
+        for candidate_model in fallback_chain {
+            let request_body = Self::with_max_output_tokens(request_body(&candidate_model));
+            match Self::generate_content(&candidate_model, request_body).await {
+                Ok(response) => {
+                    if candidate_model != model {
+                        log_event(
+                            "model_fallback",
+                            json!({ "requested": model, "answered": candidate_model }),
+                        );
+                    }
+                    return Ok((candidate_model, response));
+                }
+                Err(e) => {
+                    let fallback_eligible = e
+                        .downcast_ref::<GoogleAiCompletionError>()
+                        .is_some_and(|e| e.is_fallback_eligible());
+                    if !fallback_eligible {
+                        return Err(e);
+                    }
+                    log_error(&format!(
+                        "Google AI Completion using {} failed, trying fallback: {}",
+                        candidate_model, e
+                    ));
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Caps the answer length at `Pricing::max_output_tokens`, so the output cost
+    /// assumed by `SearchPipeline`'s `max_cost` check is an upper bound.
+    fn with_max_output_tokens(mut request_body: serde_json::Value) -> serde_json::Value {
+        if let Some(generation_config) = request_body
+            .as_object_mut()
+            .map(|body| body.entry("generationConfig").or_insert_with(|| json!({})))
+            .and_then(|generation_config| generation_config.as_object_mut())
+        {
+            generation_config
+                .entry("maxOutputTokens")
+                .or_insert_with(|| json!(Pricing::max_output_tokens()));
+        }
+        request_body
+    }
+
+    #[tracing::instrument(skip(request_body))]
+    async fn generate_content(
+        model: &str,
+        request_body: serde_json::Value,
+    ) -> Result<GoogleAiGenerateContentResponse, Box<dyn Error>> {
+        let gemini_api_key = std::env::var("GEMINI_API_KEY").unwrap();
+        let client = Upstreams::client(Upstream::Gemini);
+        let mut headers = HeaderMap::new();
+        let start_time = Instant::now();
+        headers.insert(
+            "Content-Type".parse::<HeaderName>().unwrap(),
+            "application/json".parse::<HeaderValue>().unwrap(),
+        );
+
+        let function = "generateContent";
+
+        let google_ai_completion_response = match Fixtures::send(
+            FixtureUpstream::Gemini,
+            client
+                .post(Upstreams::gemini_url(model, function))
+                .query(&[("key", &gemini_api_key)])
+                .body(serde_json::to_string(&request_body).unwrap())
+                .headers(headers),
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                Metrics::record_gemini_request(model, function, "error");
+                log_error(&format!("Request failed: {}", e));
+                return Err(format!("Request failed: {}", e).into());
+            }
+        };
+
+        if !google_ai_completion_response.status().is_success() {
+            Metrics::record_gemini_request(
+                model,
+                function,
+                google_ai_completion_response.status().as_str(),
+            );
+            return Err(GoogleAiCompletionError::Http {
+                status: google_ai_completion_response.status(),
+            }
+            .into());
+        }
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!(
+            "Google AI Completion using {} time taken: {:?}",
+            model,
+            duration
+        );
+
+        match google_ai_completion_response
+            .json::<GoogleAiGenerateContentResponse>()
+            .await
+        {
+            Ok(response) => {
+                Metrics::record_gemini_request(model, function, "success");
+                if let Some(usage_metadata) = &response.usage_metadata {
+                    Metrics::record_gemini_tokens(model, usage_metadata);
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                Metrics::record_gemini_request(model, function, "invalid_response");
+                log_error(&format!("Failed to parse JSON response: {}", e));
+                Err(format!("Failed to parse JSON response: {}", e).into())
+            }
+        }
+    }
+}
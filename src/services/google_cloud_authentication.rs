@@ -8,7 +8,7 @@ pub struct GoogleCloudAuthentication;
 impl GoogleCloudAuthentication {
     /// Creates a Google Cloud Authenticated Client
     /// # Usage
-    /// ```
+    /// ```ignore
     /// use crate::services::google_cloud_authentication::GoogleCloudAuthentication;
     /// let client = GoogleCloudAuthentication::get_authenticated_client().await.unwrap();
     /// ```
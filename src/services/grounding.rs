@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::models::google_ai_models::GroundingMetadata;
+
+pub struct Grounding;
+
+impl Grounding {
+    /// Adds inline `[n](uri)` citations after each grounded segment and appends a
+    /// `Sources:` list, matching the citation style of the Custom Search pipeline.
+    pub fn cite(text: &str, grounding_metadata: Option<&GroundingMetadata>) -> String {
+        let grounding_metadata = match grounding_metadata {
+            Some(metadata) if !metadata.grounding_chunks.is_empty() => metadata,
+            _ => return text.to_string(),
+        };
+
+        let sources: Vec<(String, String)> = grounding_metadata
+            .grounding_chunks
+            .iter()
+            .map(|chunk| match &chunk.web {
+                Some(web) => {
+                    let uri = web.uri.clone().unwrap_or_default();
+                    let title = web.title.clone().unwrap_or_else(|| uri.clone());
+                    (title, uri)
+                }
+                None => (String::new(), String::new()),
+            })
+            .collect();
+
+        // Segment indices are byte offsets into the answer text. Offsets past the
+        // end or inside a multi-byte character are moved to the next character
+        // boundary, and supports ending at the same offset share one citation run.
+        let mut insertions: BTreeMap<usize, Vec<u32>> = BTreeMap::new();
+        for support in &grounding_metadata.grounding_supports {
+            let Some(segment) = &support.segment else {
+                continue;
+            };
+            let indices: Vec<u32> = support
+                .grounding_chunk_indices
+                .iter()
+                .copied()
+                .filter(|index| {
+                    sources
+                        .get(*index as usize)
+                        .is_some_and(|(_, uri)| !uri.is_empty())
+                })
+                .collect();
+            if indices.is_empty() {
+                continue;
+            }
+
+            let citations = insertions
+                .entry(Self::char_boundary(text, segment.end_index as usize))
+                .or_default();
+            for index in indices {
+                if !citations.contains(&index) {
+                    citations.push(index);
+                }
+            }
+        }
+
+        let mut cited_text = text.to_string();
+        for (end_index, indices) in insertions.into_iter().rev() {
+            let citations: String = indices
+                .iter()
+                .map(|index| format!(" [{}]({})", index + 1, sources[*index as usize].1))
+                .collect();
+            cited_text.insert_str(end_index, &citations);
+        }
+
+        cited_text.push_str("\n\nSources:\n");
+        for (index, (title, uri)) in sources.iter().enumerate() {
+            if uri.is_empty() {
+                continue;
+            }
+            cited_text.push_str(&format!("    - [{}] [{}]({})\n", index + 1, title, uri));
+        }
+
+        cited_text
+    }
+
+    /// The first character boundary at or after `index`, at most the text's length.
+    fn char_boundary(text: &str, index: usize) -> usize {
+        let mut index = index.min(text.len());
+        while !text.is_char_boundary(index) {
+            index += 1;
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metadata(supports: serde_json::Value) -> GroundingMetadata {
+        serde_json::from_value(json!({
+            "groundingChunks": [
+                { "web": { "uri": "https://a.example", "title": "A" } },
+                { "web": { "uri": "https://b.example", "title": "B" } },
+                { "web": { "title": "No URI" } },
+            ],
+            "groundingSupports": supports,
+        }))
+        .unwrap()
+    }
+
+    fn support(end_index: usize, indices: &[u32]) -> serde_json::Value {
+        json!({ "segment": { "endIndex": end_index }, "groundingChunkIndices": indices })
+    }
+
+    #[test]
+    fn cites_segments_and_lists_sources_by_chunk_number() {
+        let text = "Paris is the capital. It is in France.";
+        let metadata = metadata(json!([support(21, &[0]), support(38, &[1, 2])]));
+
+        assert_eq!(
+            Grounding::cite(text, Some(&metadata)),
+            "Paris is the capital. [1](https://a.example) It is in France. [2](https://b.example)\
+             \n\nSources:\n    - [1] [A](https://a.example)\n    - [2] [B](https://b.example)\n"
+        );
+    }
+
+    #[test]
+    fn merges_supports_ending_at_the_same_offset() {
+        let text = "Paris is the capital.";
+        let metadata = metadata(json!([support(21, &[1]), support(21, &[0, 1])]));
+
+        let cited = Grounding::cite(text, Some(&metadata));
+        assert!(cited.starts_with(
+            "Paris is the capital. [2](https://b.example) [1](https://a.example)\n\nSources:"
+        ));
+    }
+
+    #[test]
+    fn moves_offsets_inside_multi_byte_characters_to_the_next_boundary() {
+        // "é" is bytes 3..5, so offset 4 falls inside it; 100 is past the end.
+        let text = "Café über alles";
+        let metadata = metadata(json!([support(4, &[0]), support(100, &[1])]));
+
+        let cited = Grounding::cite(text, Some(&metadata));
+        assert!(cited.starts_with(
+            "Café [1](https://a.example) über alles [2](https://b.example)\n\nSources:"
+        ));
+    }
+
+    #[test]
+    fn leaves_text_without_grounding_chunks_unchanged() {
+        assert_eq!(Grounding::cite("Plain answer", None), "Plain answer");
+    }
+}
@@ -0,0 +1,306 @@
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{
+    decode, decode_header, Algorithm, AlgorithmFamily, DecodingKey, Header, Validation,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use std::{error::Error, fmt, fs, str::FromStr};
+
+use crate::constants::config::{
+    DEFAULT_RATE_LIMIT_TIER, JWKS_CACHE_TTL_SECS, JWKS_FETCH_TIMEOUT_MS, JWKS_MIN_REFRESH_SECS,
+    JWT_LEEWAY_SECS,
+};
+use crate::constants::utility::log_error;
+use crate::models::auth_models::{AuthMethod, Principal};
+
+#[derive(Debug)]
+pub enum JwtError {
+    /// Neither `JWT_KEY_FILE`, `JWT_JWKS_URL` nor `JWT_ISSUER` is set
+    NotConfigured,
+    /// The key file or JWKS couldn't be loaded
+    KeyUnavailable(String),
+    /// No key in the JWKS matches the token's `kid`
+    UnknownKey(Option<String>),
+    /// Bad signature, algorithm, audience, issuer or expiry
+    Invalid(String),
+    MissingScopes(Vec<String>),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConfigured => write!(f, "JWT authentication has no signing keys configured"),
+            Self::KeyUnavailable(e) => write!(f, "JWT signing keys unavailable: {}", e),
+            Self::UnknownKey(Some(kid)) => write!(f, "No signing key with kid {}", kid),
+            Self::UnknownKey(None) => write!(f, "Token has no kid and the JWKS has several keys"),
+            Self::Invalid(e) => write!(f, "Invalid token: {}", e),
+            Self::MissingScopes(scopes) => write!(f, "Missing scopes: {}", scopes.join(" ")),
+        }
+    }
+}
+
+impl Error for JwtError {}
+
+#[derive(Debug, Deserialize)]
+struct OpenIdConfiguration {
+    jwks_uri: String,
+}
+
+struct CachedJwks {
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+/// JWKS by URL, and JWKS URLs discovered by issuer.
+static JWKS_CACHE: LazyLock<Mutex<HashMap<String, CachedJwks>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static JWKS_URIS: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Validates bearer JWTs, e.g. OIDC access tokens, for `AUTH_MODES=jwt`.
+///
+/// Signing keys come from `JWT_KEY_FILE` (a PEM public key, or the shared secret
+/// for HS* algorithms, with `JWT_ALGORITHM` defaulting to RS256) or else from the
+/// JWKS at `JWT_JWKS_URL`, discovered from `JWT_ISSUER`'s
+/// `/.well-known/openid-configuration` if unset. `JWT_ISSUER` and `JWT_AUDIENCE`
+/// (comma separated) are checked when set.
+pub struct JwtAuth;
+
+impl JwtAuth {
+    pub fn is_configured() -> bool {
+        ["JWT_KEY_FILE", "JWT_JWKS_URL", "JWT_ISSUER"]
+            .iter()
+            .any(|name| Self::setting(name).is_some())
+    }
+
+    /// Whether a bearer token looks like a JWT rather than an API key.
+    pub fn is_jwt(token: &str) -> bool {
+        token.split('.').count() == 3
+    }
+
+    pub async fn verify(token: &str) -> Result<Principal, JwtError> {
+        let header = decode_header(token).map_err(|e| JwtError::Invalid(e.to_string()))?;
+        let (key, algorithm) = Self::decoding_key(&header).await?;
+        if header.alg != algorithm || key.family() != algorithm.family() {
+            return Err(JwtError::Invalid(format!(
+                "{:?} is not allowed for this key",
+                header.alg
+            )));
+        }
+
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = JWT_LEEWAY_SECS;
+        match Self::setting("JWT_AUDIENCE") {
+            Some(audience) => validation.set_audience(&Self::split(&audience, ',')),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = Self::setting("JWT_ISSUER") {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|e| JwtError::Invalid(e.to_string()))?
+            .claims;
+        Self::principal(&claims)
+    }
+
+    /// Maps the claims to scopes (`JWT_SCOPES_CLAIM`, defaulting to `scope` or
+    /// `scp`) and a rate-limit tier (`JWT_TIER_CLAIM`, defaulting to `tier`, or
+    /// the first scope listed in `JWT_SCOPE_TIERS`), and checks `JWT_REQUIRED_SCOPES`.
+    fn principal(claims: &Map<String, Value>) -> Result<Principal, JwtError> {
+        let scopes_claim = Self::setting("JWT_SCOPES_CLAIM");
+        let scopes: Vec<String> = match scopes_claim.as_deref() {
+            Some(name) => claims.get(name),
+            None => claims.get("scope").or(claims.get("scp")),
+        }
+        .map(|value| match value {
+            Value::String(scopes) => Self::split(scopes, ' '),
+            Value::Array(scopes) => scopes
+                .iter()
+                .filter_map(|scope| scope.as_str().map(|scope| scope.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        })
+        .unwrap_or_default();
+
+        let missing_scopes: Vec<String> = Self::setting("JWT_REQUIRED_SCOPES")
+            .map(|required| Self::split(&required, ','))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|scope| !scopes.contains(scope))
+            .collect();
+        if !missing_scopes.is_empty() {
+            return Err(JwtError::MissingScopes(missing_scopes));
+        }
+
+        let tier_claim = Self::setting("JWT_TIER_CLAIM").unwrap_or("tier".to_string());
+        let tier = claims
+            .get(&tier_claim)
+            .and_then(|tier| tier.as_str())
+            .map(|tier| tier.to_string())
+            .or_else(|| {
+                let scope_tiers = Self::scope_tiers();
+                scopes
+                    .iter()
+                    .find_map(|scope| scope_tiers.get(scope).cloned())
+            })
+            .unwrap_or(DEFAULT_RATE_LIMIT_TIER.to_string());
+
+        Ok(Principal {
+            method: AuthMethod::Jwt,
+            subject: claims
+                .get("sub")
+                .and_then(|subject| subject.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            scopes,
+            tier,
+        })
+    }
+
+    /// `JWT_SCOPE_TIERS`, a JSON object of scope to rate-limit tier.
+    fn scope_tiers() -> HashMap<String, String> {
+        match Self::setting("JWT_SCOPE_TIERS") {
+            Some(tiers) => serde_json::from_str(&tiers).unwrap_or_else(|e| {
+                log_error(&format!("Invalid JWT_SCOPE_TIERS: {}", e));
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        }
+    }
+
+    async fn decoding_key(header: &Header) -> Result<(DecodingKey, Algorithm), JwtError> {
+        if let Some(path) = Self::setting("JWT_KEY_FILE") {
+            let algorithm = match Self::setting("JWT_ALGORITHM") {
+                Some(algorithm) => Algorithm::from_str(&algorithm)
+                    .map_err(|e| JwtError::KeyUnavailable(format!("JWT_ALGORITHM: {}", e)))?,
+                None => Algorithm::RS256,
+            };
+            let key = fs::read(&path)
+                .map_err(|e| JwtError::KeyUnavailable(format!("{}: {}", path, e)))?;
+            let key = match algorithm.family() {
+                AlgorithmFamily::Hmac => Ok(DecodingKey::from_secret(key.trim_ascii())),
+                AlgorithmFamily::Rsa => DecodingKey::from_rsa_pem(&key),
+                AlgorithmFamily::Ec => DecodingKey::from_ec_pem(&key),
+                AlgorithmFamily::Ed => DecodingKey::from_ed_pem(&key),
+            }
+            .map_err(|e| JwtError::KeyUnavailable(format!("{}: {}", path, e)))?;
+            return Ok((key, algorithm));
+        }
+
+        let jwks_url = Self::jwks_url().await?;
+        let jwk = Self::find_jwk(&jwks_url, header.kid.as_deref()).await?;
+        // Keys that declare their algorithm only verify that algorithm.
+        let algorithm = match &jwk.common.key_algorithm {
+            Some(key_algorithm) => Algorithm::from_str(&key_algorithm.to_string())
+                .map_err(|e| JwtError::Invalid(format!("{}: {}", key_algorithm, e)))?,
+            None => header.alg,
+        };
+        let key = DecodingKey::from_jwk(&jwk).map_err(|e| JwtError::Invalid(e.to_string()))?;
+        Ok((key, algorithm))
+    }
+
+    async fn jwks_url() -> Result<String, JwtError> {
+        if let Some(url) = Self::setting("JWT_JWKS_URL") {
+            return Ok(url);
+        }
+        let issuer = Self::setting("JWT_ISSUER").ok_or(JwtError::NotConfigured)?;
+
+        let cached_url = JWKS_URIS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&issuer)
+            .cloned();
+        if let Some(url) = cached_url {
+            return Ok(url);
+        }
+
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let configuration: OpenIdConfiguration = Self::fetch_json(&discovery_url).await?;
+        JWKS_URIS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(issuer, configuration.jwks_uri.clone());
+        Ok(configuration.jwks_uri)
+    }
+
+    /// The key for `kid` (or the only key when the token has none) from the
+    /// cached JWKS, refetching it when it's stale or the key is unknown, so
+    /// rotated keys are picked up.
+    async fn find_jwk(jwks_url: &str, kid: Option<&str>) -> Result<Jwk, JwtError> {
+        let cached = JWKS_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(jwks_url)
+            .filter(|cached| cached.fetched_at.elapsed() < Duration::from_secs(JWKS_CACHE_TTL_SECS))
+            .map(|cached| (Self::select(&cached.jwks, kid), cached.fetched_at));
+
+        match cached {
+            Some((Some(jwk), _)) => return Ok(jwk),
+            Some((None, fetched_at))
+                if fetched_at.elapsed() < Duration::from_secs(JWKS_MIN_REFRESH_SECS) =>
+            {
+                return Err(JwtError::UnknownKey(kid.map(|kid| kid.to_string())));
+            }
+            _ => {}
+        }
+
+        let jwks: JwkSet = Self::fetch_json(jwks_url).await?;
+        let jwk = Self::select(&jwks, kid);
+        JWKS_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                jwks_url.to_string(),
+                CachedJwks {
+                    jwks,
+                    fetched_at: Instant::now(),
+                },
+            );
+        jwk.ok_or(JwtError::UnknownKey(kid.map(|kid| kid.to_string())))
+    }
+
+    fn select(jwks: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+        match kid {
+            Some(kid) => jwks.find(kid).cloned(),
+            None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+            None => None,
+        }
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, JwtError> {
+        let response = Client::new()
+            .get(url)
+            .timeout(Duration::from_millis(JWKS_FETCH_TIMEOUT_MS))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| JwtError::KeyUnavailable(e.to_string()))?;
+        response
+            .json::<T>()
+            .await
+            .map_err(|e| JwtError::KeyUnavailable(format!("{}: {}", url, e)))
+    }
+
+    fn split(value: &str, separator: char) -> Vec<String> {
+        value
+            .split(separator)
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    fn setting(name: &str) -> Option<String> {
+        std::env::var(name)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+}
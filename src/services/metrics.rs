@@ -0,0 +1,210 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::models::google_ai_models::UsageMetadata;
+use crate::services::model_registry::ModelRegistry;
+
+struct MetricsRegistry {
+    registry: Registry,
+    request_duration: HistogramVec,
+    stage_duration: HistogramVec,
+    custom_search_requests: IntCounterVec,
+    gemini_requests: IntCounterVec,
+    gemini_tokens: IntCounterVec,
+    scrape_results: IntCounterVec,
+    cache_lookups: IntCounterVec,
+    rate_limit_rejections: IntCounterVec,
+}
+
+impl MetricsRegistry {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("googlexity".to_string()), None).expect("metrics registry");
+        let latency_buckets = vec![
+            0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0,
+        ];
+
+        let metrics = MetricsRegistry {
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "HTTP request latency by route",
+                )
+                .buckets(latency_buckets.clone()),
+                &["route", "method", "status"],
+            )
+            .expect("metric"),
+            stage_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "stage_duration_seconds",
+                    "Latency of each stage of the search pipeline",
+                )
+                .buckets(latency_buckets),
+                &["stage"],
+            )
+            .expect("metric"),
+            custom_search_requests: IntCounterVec::new(
+                Opts::new(
+                    "custom_search_requests_total",
+                    "Custom Search API calls by outcome",
+                ),
+                &["outcome"],
+            )
+            .expect("metric"),
+            gemini_requests: IntCounterVec::new(
+                Opts::new(
+                    "gemini_requests_total",
+                    "Gemini API calls by model, method and outcome",
+                ),
+                &["model", "method", "outcome"],
+            )
+            .expect("metric"),
+            gemini_tokens: IntCounterVec::new(
+                Opts::new("gemini_tokens_total", "Gemini tokens by model and kind"),
+                &["model", "kind"],
+            )
+            .expect("metric"),
+            scrape_results: IntCounterVec::new(
+                Opts::new(
+                    "scrape_results_total",
+                    "Scraped search results by outcome (success or skip reason)",
+                ),
+                &["outcome"],
+            )
+            .expect("metric"),
+            cache_lookups: IntCounterVec::new(
+                Opts::new("cache_lookups_total", "Cache lookups by cache and result"),
+                &["cache", "result"],
+            )
+            .expect("metric"),
+            rate_limit_rejections: IntCounterVec::new(
+                Opts::new(
+                    "rate_limit_rejections_total",
+                    "Requests rejected by rate or concurrency limits",
+                ),
+                &["scope"],
+            )
+            .expect("metric"),
+            registry,
+        };
+
+        for collector in [
+            Box::new(metrics.request_duration.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.stage_duration.clone()),
+            Box::new(metrics.custom_search_requests.clone()),
+            Box::new(metrics.gemini_requests.clone()),
+            Box::new(metrics.gemini_tokens.clone()),
+            Box::new(metrics.scrape_results.clone()),
+            Box::new(metrics.cache_lookups.clone()),
+            Box::new(metrics.rate_limit_rejections.clone()),
+        ] {
+            metrics
+                .registry
+                .register(collector)
+                .expect("register metric");
+        }
+
+        metrics
+    }
+}
+
+static METRICS: LazyLock<MetricsRegistry> = LazyLock::new(MetricsRegistry::new);
+
+/// Prometheus metrics, served at `/metrics`.
+pub struct Metrics;
+
+impl Metrics {
+    pub fn observe_request(route: &str, method: &str, status: u16, duration: Duration) {
+        METRICS
+            .request_duration
+            .with_label_values(&[route, method, &status.to_string()])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// See `StageTiming::observe`, which stages are timed through.
+    pub fn observe_stage(stage: &str, duration: Duration) {
+        METRICS
+            .stage_duration
+            .with_label_values(&[stage])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// `outcome` is `success`, `error` (no response) or the HTTP status.
+    pub fn record_custom_search(outcome: &str) {
+        METRICS
+            .custom_search_requests
+            .with_label_values(&[outcome])
+            .inc();
+    }
+
+    /// `outcome` is `success`, `error` (no response) or the HTTP status.
+    pub fn record_gemini_request(model: &str, method: &str, outcome: &str) {
+        METRICS
+            .gemini_requests
+            .with_label_values(&[Self::model_label(model), method, outcome])
+            .inc();
+    }
+
+    pub fn record_gemini_tokens(model: &str, usage: &UsageMetadata) {
+        let model = Self::model_label(model);
+        METRICS
+            .gemini_tokens
+            .with_label_values(&[model, "prompt"])
+            .inc_by(u64::from(usage.prompt_token_count));
+        METRICS
+            .gemini_tokens
+            .with_label_values(&[model, "candidates"])
+            .inc_by(u64::from(usage.candidates_token_count));
+    }
+
+    /// Models missing from the registry are labelled `other`, so model names
+    /// made up by callers don't each add new series.
+    fn model_label(model: &str) -> &str {
+        if ModelRegistry::get(model).is_some() {
+            model
+        } else {
+            "other"
+        }
+    }
+
+    pub fn record_scrape(outcome: &str) {
+        METRICS.scrape_results.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_cache_lookup(cache: &str, hit: bool) {
+        METRICS
+            .cache_lookups
+            .with_label_values(&[cache, if hit { "hit" } else { "miss" }])
+            .inc();
+    }
+
+    pub fn record_rate_limit_rejection(scope: &str) {
+        METRICS
+            .rate_limit_rejections
+            .with_label_values(&[scope])
+            .inc();
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render() -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+            return format!("# Failed to encode metrics: {}\n", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_unknown_models_as_other() {
+        assert_eq!(Metrics::model_label("gemini-2.5-flash"), "gemini-2.5-flash");
+        assert_eq!(Metrics::model_label("made-up-model-1"), "other");
+    }
+}
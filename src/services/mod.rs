@@ -1,2 +1,23 @@
+// Not currently used, kept for Vertex AI / service account access.
+pub mod citation_verification;
+pub mod concurrency;
+pub mod context_trimming;
+pub mod domain_filtering;
+pub mod fixtures;
+pub mod google_ai;
+#[allow(dead_code)]
 pub mod google_cloud_authentication;
+pub mod grounding;
+pub mod jwt_auth;
+pub mod metrics;
+pub mod model_registry;
+pub mod page_rendering;
+pub mod pricing;
+pub mod search_jobs;
+pub mod search_pipeline;
+pub mod site_extractors;
+pub mod ssrf_guard;
+pub mod stage_timing;
+pub mod telemetry;
+pub mod upstreams;
 pub mod web_scraping;
@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+use crate::constants::config::DEFAULT_MODEL_REGISTRY;
+use crate::constants::utility::log_error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDefinition {
+    pub name: String,
+    pub context_window: u64,
+    pub input_price: f64,
+    pub output_price: f64,
+    #[serde(default)]
+    pub json_mode: bool,
+    #[serde(default)]
+    pub tools: bool,
+    #[serde(default)]
+    pub streaming: bool,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub fallback: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModelCapability {
+    JsonMode,
+    Tools,
+}
+
+impl ModelDefinition {
+    pub fn supports(&self, capability: ModelCapability) -> bool {
+        match capability {
+            ModelCapability::JsonMode => self.json_mode,
+            ModelCapability::Tools => self.tools,
+        }
+    }
+}
+
+/// The parsed registry along with the `MODEL_REGISTRY` value it was built from.
+struct CachedRegistry {
+    overrides: Option<String>,
+    models: Arc<Vec<ModelDefinition>>,
+}
+
+static REGISTRY: LazyLock<Mutex<Option<CachedRegistry>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Which models a caller may use: those allowed by `ALLOWED_MODELS`, narrowed
+/// by the `TIER_ALLOWED_MODELS` entry for the caller's rate-limit tier, if any.
+#[derive(Debug, Clone, Default)]
+pub struct ModelAccess {
+    tier: Option<String>,
+}
+
+impl ModelAccess {
+    pub fn for_tier(tier: &str) -> Self {
+        ModelAccess {
+            tier: Some(tier.to_string()),
+        }
+    }
+
+    /// For models the server picks itself (e.g. for query optimisation), which
+    /// only `ALLOWED_MODELS` restricts.
+    pub fn server() -> Self {
+        Self::default()
+    }
+
+    pub fn allows(&self, model: &str) -> bool {
+        let allowed_globally = ModelRegistry::allowed_models()
+            .is_none_or(|allowed_models| allowed_models.iter().any(|allowed| allowed == model));
+        let allowed_for_tier = self
+            .tier
+            .as_deref()
+            .and_then(ModelRegistry::tier_allowed_models)
+            .is_none_or(|allowed_models| allowed_models.iter().any(|allowed| allowed == model));
+
+        allowed_globally && allowed_for_tier
+    }
+}
+
+pub struct ModelRegistry;
+
+impl ModelRegistry {
+    /// The built-in registry with entries from the `MODEL_REGISTRY` env var
+    /// (a JSON array of model definitions) replacing or adding to it by name.
+    /// Parsed once, and again only if `MODEL_REGISTRY` changes.
+    pub fn models() -> Arc<Vec<ModelDefinition>> {
+        let overrides = std::env::var("MODEL_REGISTRY").ok();
+        let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+        match registry.as_ref() {
+            Some(cached) if cached.overrides == overrides => Arc::clone(&cached.models),
+            _ => {
+                let models = Arc::new(Self::parse(overrides.as_deref()));
+                *registry = Some(CachedRegistry {
+                    overrides,
+                    models: Arc::clone(&models),
+                });
+                models
+            }
+        }
+    }
+
+    fn parse(overrides: Option<&str>) -> Vec<ModelDefinition> {
+        let mut models: Vec<ModelDefinition> = DEFAULT_MODEL_REGISTRY
+            .iter()
+            .map(|model| ModelDefinition {
+                name: model.name.to_string(),
+                context_window: model.context_window,
+                input_price: model.input_price,
+                output_price: model.output_price,
+                json_mode: model.json_mode,
+                tools: model.tools,
+                streaming: model.streaming,
+                deprecated: model.deprecated,
+                fallback: model.fallback.iter().map(|name| name.to_string()).collect(),
+            })
+            .collect();
+
+        if let Some(overrides) = overrides {
+            match serde_json::from_str::<Vec<ModelDefinition>>(overrides) {
+                Ok(overrides) => {
+                    for model in overrides {
+                        models.retain(|existing| existing.name != model.name);
+                        models.push(model);
+                    }
+                }
+                Err(e) => log_error(&format!("Invalid MODEL_REGISTRY: {}", e)),
+            }
+        }
+
+        models
+    }
+
+    /// Looks up a model by exact name, then by longest matching name prefix, so
+    /// `gemini-2.5-pro-preview-05-06` uses the `gemini-2.5-pro` entry.
+    pub fn get(model: &str) -> Option<ModelDefinition> {
+        let models = Self::models();
+        if let Some(definition) = models.iter().find(|definition| definition.name == model) {
+            return Some(definition.clone());
+        }
+
+        models
+            .iter()
+            .filter(|definition| model.starts_with(definition.name.as_str()))
+            .max_by_key(|definition| definition.name.len())
+            .cloned()
+    }
+
+    /// Models listed in the `ALLOWED_MODELS` env var (comma separated), if set.
+    pub fn allowed_models() -> Option<Vec<String>> {
+        std::env::var("ALLOWED_MODELS")
+            .ok()
+            .map(|models| {
+                models
+                    .split(',')
+                    .map(|model| model.trim().to_string())
+                    .filter(|model| !model.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .filter(|models| !models.is_empty())
+    }
+
+    /// The models a rate-limit tier may use from the `TIER_ALLOWED_MODELS` env
+    /// var (a JSON object of tier to model names), if the tier is listed.
+    pub fn tier_allowed_models(tier: &str) -> Option<Vec<String>> {
+        std::env::var("TIER_ALLOWED_MODELS")
+            .ok()
+            .filter(|tiers| !tiers.trim().is_empty())
+            .and_then(|tiers| {
+                serde_json::from_str::<HashMap<String, Vec<String>>>(&tiers)
+                    .map_err(|e| log_error(&format!("Invalid TIER_ALLOWED_MODELS: {}", e)))
+                    .ok()
+            })
+            .and_then(|mut tiers| tiers.remove(tier))
+    }
+
+    /// The requested model followed by its fallbacks (breadth first), skipping
+    /// models that are deprecated, not allowed by `access` or missing `capability`.
+    /// Models not in the registry are assumed to be usable.
+    pub fn fallback_chain(
+        model: &str,
+        capability: Option<ModelCapability>,
+        access: &ModelAccess,
+    ) -> Vec<String> {
+        let mut visited: Vec<String> = Vec::new();
+        let mut chain: Vec<String> = Vec::new();
+        let mut queue: VecDeque<String> = VecDeque::from([model.to_string()]);
+
+        while let Some(candidate) = queue.pop_front() {
+            if visited.contains(&candidate) {
+                continue;
+            }
+            visited.push(candidate.clone());
+
+            let definition = Self::get(&candidate);
+            if let Some(definition) = &definition {
+                queue.extend(definition.fallback.iter().cloned());
+            }
+
+            let usable = match &definition {
+                Some(definition) => {
+                    !definition.deprecated
+                        && capability.is_none_or(|capability| definition.supports(capability))
+                }
+                None => true,
+            };
+            if usable && access.allows(&candidate) {
+                chain.push(candidate);
+            }
+        }
+
+        chain
+    }
+}
@@ -0,0 +1,281 @@
+use futures_util::{SinkExt, StreamExt};
+use reqwest::{header::CONTENT_LOCATION, redirect::Policy, Client};
+use serde_json::{json, Value};
+use std::{error::Error, time::Duration, time::Instant};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use url::{form_urlencoded, Url};
+
+use crate::constants::config::{
+    DEFAULT_CDP_ENDPOINT, DEFAULT_RENDER_MIN_TEXT_LENGTH, DEFAULT_RENDER_TIMEOUT_MS,
+};
+use crate::constants::utility::log_error;
+use crate::services::domain_filtering::DomainFiltering;
+use crate::services::fixtures::{FixtureMode, Fixtures};
+use crate::services::ssrf_guard::SsrfGuard;
+use crate::services::stage_timing::StageTiming;
+
+/// Waits for the page to load, gives scripts a moment to render, then returns
+/// the page's final URL and its DOM.
+const RENDERED_HTML_EXPRESSION: &str = "new Promise(resolve => {
+    if (document.readyState === 'complete') resolve();
+    else window.addEventListener('load', () => resolve());
+}).then(() => new Promise(resolve => setTimeout(resolve, 1000)))
+  .then(() => ({ url: location.href, html: document.documentElement.outerHTML }))";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderBackend {
+    /// A Chrome DevTools Protocol endpoint, e.g. `chrome --headless --remote-debugging-port=9222`
+    Cdp { endpoint: String },
+    /// A render service that takes `POST {"url": ""}` and returns the rendered HTML
+    Service { url: String },
+}
+
+impl RenderBackend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cdp { .. } => "cdp",
+            Self::Service { .. } => "service",
+        }
+    }
+}
+
+pub struct PageRendering;
+
+impl PageRendering {
+    /// The configured backend: `RENDER_BACKEND=cdp` (using `CDP_ENDPOINT`) or
+    /// `RENDER_BACKEND=service` (using `RENDER_SERVICE_URL`).
+    pub fn backend() -> Option<RenderBackend> {
+        match std::env::var("RENDER_BACKEND").ok()?.trim() {
+            "cdp" => Some(RenderBackend::Cdp {
+                endpoint: std::env::var("CDP_ENDPOINT")
+                    .unwrap_or(DEFAULT_CDP_ENDPOINT.to_string())
+                    .trim_end_matches('/')
+                    .to_string(),
+            }),
+            "service" => std::env::var("RENDER_SERVICE_URL")
+                .ok()
+                .map(|url| RenderBackend::Service { url }),
+            _ => None,
+        }
+    }
+
+    /// Whether a page should be rendered: a backend is configured, the domain is
+    /// opted in with `RENDER_DOMAINS` and static scraping found too little text.
+    pub fn should_render(url: &str, static_text_length: usize) -> bool {
+        // The browser fetches pages itself, so rendered pages can't be replayed.
+        if Fixtures::mode() == FixtureMode::Replay {
+            return false;
+        }
+
+        if Self::backend().is_none() {
+            return false;
+        }
+
+        let min_text_length = std::env::var("RENDER_MIN_TEXT_LENGTH")
+            .ok()
+            .and_then(|length| length.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_RENDER_MIN_TEXT_LENGTH);
+        if static_text_length >= min_text_length {
+            return false;
+        }
+
+        let render_domains: Vec<String> = std::env::var("RENDER_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|domain| domain.trim().to_string())
+            .filter(|domain| !domain.is_empty())
+            .collect();
+
+        DomainFiltering::host(url)
+            .is_some_and(|host| DomainFiltering::matches_any(&host, &render_domains))
+    }
+
+    /// Renders the page with the configured backend within `RENDER_TIMEOUT_MS`,
+    /// returning its HTML. The browser fetches the page itself, so the URL is
+    /// checked against the SSRF guard first and the page is rejected if the
+    /// browser ended up anywhere else. Redirects and subresources loaded by the
+    /// browser aren't guarded, which is why only `RENDER_DOMAINS` are rendered.
+    pub async fn render(url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let backend = Self::backend().ok_or("No render backend configured")?;
+        SsrfGuard::check_url(url).await?;
+        let client = &Self::client();
+        let timeout = Duration::from_millis(
+            std::env::var("RENDER_TIMEOUT_MS")
+                .ok()
+                .and_then(|timeout| timeout.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_RENDER_TIMEOUT_MS),
+        );
+        let start_time = Instant::now();
+
+        // The DevTools tab has to be closed even when rendering times out, so
+        // `render_cdp` applies the timeout itself.
+        let (final_url, html) = match &backend {
+            RenderBackend::Cdp { endpoint } => {
+                Self::render_cdp(endpoint, url, timeout, client).await?
+            }
+            RenderBackend::Service { url: service_url } => tokio::time::timeout(
+                timeout,
+                Self::render_service(service_url, url, timeout, client),
+            )
+            .await
+            .map_err(|_| Self::timed_out(timeout))??,
+        };
+
+        if !Self::same_page(url, &final_url) {
+            return Err(format!("Rendered page redirected to {}", final_url).into());
+        }
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Rendering time taken for {}: {:?}", url, duration);
+        StageTiming::observe("rendering", duration);
+
+        Ok(html)
+    }
+
+    /// The client for the render backend. The backend is trusted configuration,
+    /// usually on localhost, so it isn't put through the SSRF guard, but it
+    /// doesn't follow redirects or use system proxies either. Panics if the
+    /// client can't be built.
+    fn client() -> Client {
+        Client::builder()
+            .redirect(Policy::none())
+            .no_proxy()
+            .build()
+            .expect("Failed to build the render backend client")
+    }
+
+    /// Whether the browser's final URL is the requested page, ignoring the fragment.
+    fn same_page(url: &str, final_url: &str) -> bool {
+        match (Url::parse(url), Url::parse(final_url)) {
+            (Ok(mut url), Ok(mut final_url)) => {
+                url.set_fragment(None);
+                final_url.set_fragment(None);
+                url == final_url
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the final URL from the service's `Content-Location` header, or
+    /// the requested URL if the service doesn't set it, with the HTML.
+    async fn render_service(
+        service_url: &str,
+        url: &str,
+        timeout: Duration,
+        client: &Client,
+    ) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let response = client
+            .post(service_url)
+            .json(&json!({ "url": url, "timeout": timeout.as_millis() as u64 }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP error! status: {}", response.status()).into());
+        }
+        let final_url = response
+            .headers()
+            .get(CONTENT_LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or(url)
+            .to_string();
+        Ok((final_url, response.text().await?))
+    }
+
+    /// Opens the page in a new tab, evaluates [`RENDERED_HTML_EXPRESSION`] over
+    /// the DevTools websocket within `timeout` and closes the tab again, also
+    /// when the evaluation fails or times out.
+    async fn render_cdp(
+        endpoint: &str,
+        url: &str,
+        timeout: Duration,
+        client: &Client,
+    ) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // DevTools takes the page URL as the raw query, so it's encoded to keep
+        // any `#fragment` or `&` from being cut off.
+        let encoded_url: String = form_urlencoded::byte_serialize(url.as_bytes()).collect();
+        let target: Value = tokio::time::timeout_at(deadline, async {
+            client
+                .put(format!("{}/json/new?{}", endpoint, encoded_url))
+                .send()
+                .await?
+                .json::<Value>()
+                .await
+        })
+        .await
+        .map_err(|_| Self::timed_out(timeout))??;
+        let target_id = target["id"].as_str().unwrap_or_default().to_string();
+
+        let html = match target["webSocketDebuggerUrl"].as_str() {
+            Some(websocket_url) => {
+                tokio::time::timeout_at(deadline, Self::evaluate_rendered_html(websocket_url))
+                    .await
+                    .unwrap_or_else(|_| Err(Self::timed_out(timeout).into()))
+            }
+            None => Err("No webSocketDebuggerUrl in DevTools response".into()),
+        };
+
+        if let Err(e) = client
+            .get(format!("{}/json/close/{}", endpoint, target_id))
+            .send()
+            .await
+        {
+            log_error(&format!("Failed to close DevTools tab: {}", e));
+        }
+
+        html
+    }
+
+    fn timed_out(timeout: Duration) -> String {
+        format!("Rendering timed out after {:?}", timeout)
+    }
+
+    /// The page's final URL and rendered HTML.
+    async fn evaluate_rendered_html(
+        websocket_url: &str,
+    ) -> Result<(String, String), Box<dyn Error + Send + Sync>> {
+        let (mut websocket, _) = connect_async(websocket_url).await?;
+
+        websocket
+            .send(Message::Text(
+                json!({
+                    "id": 1,
+                    "method": "Runtime.evaluate",
+                    "params": {
+                        "expression": RENDERED_HTML_EXPRESSION,
+                        "awaitPromise": true,
+                        "returnByValue": true
+                    }
+                })
+                .to_string(),
+            ))
+            .await?;
+
+        while let Some(message) = websocket.next().await {
+            let message = message?;
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let response: Value = serde_json::from_str(&text)?;
+            if response["id"] != 1 {
+                continue;
+            }
+            if let Some(exception) = response["result"]["exceptionDetails"].as_object() {
+                return Err(format!("Page evaluation failed: {:?}", exception).into());
+            }
+
+            let _ = websocket.close(None).await;
+            let page = &response["result"]["result"]["value"];
+            return match (page["url"].as_str(), page["html"].as_str()) {
+                (Some(url), Some(html)) => Ok((url.to_string(), html.to_string())),
+                _ => Err("No HTML returned from DevTools".into()),
+            };
+        }
+
+        Err("DevTools websocket closed before returning the page".into())
+    }
+}
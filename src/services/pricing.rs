@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::constants::config::{
+    DEFAULT_GROUNDED_QUERY_PRICE, DEFAULT_MAX_OUTPUT_TOKENS, DEFAULT_SEARCH_QUERY_PRICE,
+};
+use crate::constants::utility::log_error;
+use crate::services::model_registry::ModelRegistry;
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+pub struct Pricing;
+
+impl Pricing {
+    /// Looks up the price for a model from the model registry.
+    /// Entries in the `MODEL_PRICES` env var (JSON, keyed by model name prefix)
+    /// take precedence over the registry.
+    pub fn model_price(model: &str) -> Option<ModelPrice> {
+        if let Ok(overrides) = std::env::var("MODEL_PRICES") {
+            match serde_json::from_str::<HashMap<String, ModelPrice>>(&overrides) {
+                Ok(overrides) => {
+                    let price = overrides
+                        .into_iter()
+                        .filter(|(name, _)| model.starts_with(name.as_str()))
+                        .max_by_key(|(name, _)| name.len())
+                        .map(|(_, price)| price);
+                    if price.is_some() {
+                        return price;
+                    }
+                }
+                Err(e) => log_error(&format!("Invalid MODEL_PRICES: {}", e)),
+            }
+        }
+
+        ModelRegistry::get(model).map(|definition| ModelPrice {
+            input: definition.input_price,
+            output: definition.output_price,
+        })
+    }
+
+    pub fn completion_cost(model: &str, prompt_tokens: u64, candidates_tokens: u64) -> f64 {
+        match Self::model_price(model) {
+            Some(price) => {
+                (prompt_tokens as f64 * price.input + candidates_tokens as f64 * price.output)
+                    / 1_000_000.0
+            }
+            None => {
+                log_error(&format!("No price configured for model {}", model));
+                0.0
+            }
+        }
+    }
+
+    /// Worst-case cost used before a call is made: the prompt at ~4 characters
+    /// per token plus a full `max_output_tokens` answer.
+    pub fn estimate_completion_cost(model: &str, prompt: &str) -> f64 {
+        Self::completion_cost(
+            model,
+            Self::estimate_tokens(prompt),
+            Self::max_output_tokens(),
+        )
+    }
+
+    /// `maxOutputTokens` for Gemini requests, from `MAX_OUTPUT_TOKENS`.
+    pub fn max_output_tokens() -> u64 {
+        std::env::var("MAX_OUTPUT_TOKENS")
+            .ok()
+            .and_then(|tokens| tokens.parse::<u64>().ok())
+            .filter(|tokens| *tokens > 0)
+            .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS)
+    }
+
+    pub fn estimate_tokens(text: &str) -> u64 {
+        (text.chars().count() as u64).div_ceil(4)
+    }
+
+    pub fn search_query_cost() -> f64 {
+        Self::env_price("SEARCH_QUERY_PRICE", DEFAULT_SEARCH_QUERY_PRICE)
+    }
+
+    pub fn grounded_query_cost() -> f64 {
+        Self::env_price("GROUNDED_QUERY_PRICE", DEFAULT_GROUNDED_QUERY_PRICE)
+    }
+
+    fn env_price(name: &str, default: f64) -> f64 {
+        std::env::var(name)
+            .ok()
+            .and_then(|price| price.parse::<f64>().ok())
+            .unwrap_or(default)
+    }
+}
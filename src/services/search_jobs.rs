@@ -0,0 +1,415 @@
+use actix_web::body::to_bytes;
+use chrono::{SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, redirect::Policy, Client};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use std::{error::Error, fmt};
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+use url::Url;
+use uuid::Uuid;
+
+use crate::constants::config::{
+    DEFAULT_SEARCH_JOB_MAX_PENDING, DEFAULT_SEARCH_JOB_MAX_PENDING_PER_KEY,
+    DEFAULT_SEARCH_JOB_WORKERS, SEARCH_JOB_RETENTION_SECS, WEBHOOK_MAX_ATTEMPTS,
+    WEBHOOK_SIGNATURE_HEADER, WEBHOOK_TIMEOUT_MS, WEBHOOK_TIMESTAMP_HEADER,
+};
+use crate::constants::utility::{log_error, log_event};
+use crate::models::google_search_models::{DomainPolicy, ResponseFormat, SearchRequest};
+use crate::models::job_models::{
+    JobStatus, SearchJob, SearchJobRequest, StageProgress, WebhookDelivery,
+};
+use crate::services::concurrency::ConcurrencyLimits;
+use crate::services::model_registry::ModelAccess;
+use crate::services::search_pipeline::SearchPipeline;
+use crate::services::ssrf_guard::SsrfGuard;
+use crate::services::stage_timing::StageTiming;
+
+/// A job with when it finished, so it can be dropped after the retention period.
+struct StoredJob {
+    job: SearchJob,
+    finished: Option<Instant>,
+}
+
+static JOBS: LazyLock<Mutex<HashMap<String, StoredJob>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Sized from `SEARCH_JOB_WORKERS` when the first job runs.
+static WORKERS: LazyLock<Semaphore> = LazyLock::new(|| {
+    Semaphore::new(SearchJobs::env_usize(
+        "SEARCH_JOB_WORKERS",
+        DEFAULT_SEARCH_JOB_WORKERS,
+    ))
+});
+
+/// Who a job runs for, resolved from the request when it's submitted.
+#[derive(Debug, Clone)]
+pub struct JobCaller {
+    pub subject: String,
+    pub tier: String,
+    pub domain_policy: DomainPolicy,
+    pub model_access: ModelAccess,
+}
+
+#[derive(Debug)]
+pub enum SearchJobError {
+    InvalidCallbackUrl(String),
+    /// A `callback_url` was given but `SEARCH_JOB_WEBHOOK_SECRET` isn't set
+    WebhookSecretMissing,
+    QueueFull,
+}
+
+impl SearchJobError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidCallbackUrl(_) => "invalid_callback_url",
+            Self::WebhookSecretMissing => "webhooks_not_configured",
+            Self::QueueFull => "queue_full",
+        }
+    }
+}
+
+impl fmt::Display for SearchJobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCallbackUrl(reason) => write!(f, "Invalid callback_url: {}", reason),
+            Self::WebhookSecretMissing => {
+                write!(
+                    f,
+                    "Webhooks are disabled, SEARCH_JOB_WEBHOOK_SECRET is not set"
+                )
+            }
+            Self::QueueFull => write!(f, "Too many search jobs pending, try again later"),
+        }
+    }
+}
+
+impl Error for SearchJobError {}
+
+/// Runs searches in the background on a pool of `SEARCH_JOB_WORKERS`, keeping
+/// their status and result in memory for `SEARCH_JOB_RETENTION_SECS` after they
+/// finish. At most `SEARCH_JOB_MAX_PENDING` jobs can be queued or running, and
+/// at most `SEARCH_JOB_MAX_PENDING_PER_KEY` of them for one caller.
+pub struct SearchJobs;
+
+impl SearchJobs {
+    /// Queues the search and returns the new job straight away.
+    pub fn submit(
+        request: SearchJobRequest,
+        caller: JobCaller,
+    ) -> Result<SearchJob, SearchJobError> {
+        if let Some(callback_url) = &request.callback_url {
+            Self::validate_callback_url(callback_url)?;
+            if Self::webhook_secret().is_none() {
+                return Err(SearchJobError::WebhookSecretMissing);
+            }
+        }
+
+        let job = SearchJob {
+            id: Uuid::new_v4().to_string(),
+            status: JobStatus::Queued,
+            created_at: Self::now(),
+            started_at: None,
+            finished_at: None,
+            stages: Vec::new(),
+            result: None,
+            error: None,
+            callback_url: request.callback_url.clone(),
+            webhook: None,
+            subject: caller.subject.clone(),
+        };
+
+        {
+            let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+            let retention = Duration::from_secs(SEARCH_JOB_RETENTION_SECS);
+            jobs.retain(|_, stored| stored.finished.is_none_or(|at| at.elapsed() < retention));
+
+            let max_pending =
+                Self::env_usize("SEARCH_JOB_MAX_PENDING", DEFAULT_SEARCH_JOB_MAX_PENDING);
+            let max_pending_per_key = Self::env_usize(
+                "SEARCH_JOB_MAX_PENDING_PER_KEY",
+                DEFAULT_SEARCH_JOB_MAX_PENDING_PER_KEY,
+            );
+            let pending: Vec<&SearchJob> = jobs
+                .values()
+                .filter(|stored| stored.finished.is_none())
+                .map(|stored| &stored.job)
+                .collect();
+            let pending_for_caller = pending
+                .iter()
+                .filter(|pending| pending.subject == caller.subject)
+                .count();
+            if pending.len() >= max_pending || pending_for_caller >= max_pending_per_key {
+                return Err(SearchJobError::QueueFull);
+            }
+            jobs.insert(
+                job.id.clone(),
+                StoredJob {
+                    job: job.clone(),
+                    finished: None,
+                },
+            );
+        }
+
+        log_event(
+            "search_job_queued",
+            json!({ "job_id": job.id, "query": request.search.query }),
+        );
+        let span = tracing::info_span!("search_job", job_id = %job.id);
+        actix_web::rt::spawn(
+            Self::run(job.id.clone(), caller, request.search, request.callback_url)
+                .instrument(span),
+        );
+        Ok(job)
+    }
+
+    /// The job, if it exists and belongs to `subject`.
+    pub fn get(id: &str, subject: &str) -> Option<SearchJob> {
+        Self::job(id).filter(|job| job.subject == subject)
+    }
+
+    /// Runs the normal search pipeline, always asking for a JSON answer so the
+    /// result can be stored and recording each stage as it finishes, then calls
+    /// the webhook if there is one. The job stays queued until it has a slot
+    /// under the same per-key and global concurrency limits as `/api/search`,
+    /// however long that takes, since it was already accepted. The slot is
+    /// taken before a worker, so one caller's jobs can't tie up every worker
+    /// waiting for their own slots.
+    async fn run(
+        id: String,
+        caller: JobCaller,
+        search: SearchRequest,
+        callback_url: Option<String>,
+    ) {
+        let search_permit =
+            ConcurrencyLimits::acquire_without_timeout(&caller.subject, &caller.tier).await;
+        let permit = WORKERS.acquire().await;
+        let start_time = Instant::now();
+        let (status, result, error) = Self::search(&id, &caller, search).await;
+        drop(permit);
+        drop(search_permit);
+
+        let finished_at = Self::now();
+        {
+            let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(stored) = jobs.get_mut(&id) {
+                stored.job.status = status;
+                stored.job.result = result;
+                stored.job.error = error;
+                stored.job.finished_at = Some(finished_at);
+                stored.finished = Some(Instant::now());
+            }
+        }
+        log_event(
+            "search_job_finished",
+            json!({
+                "job_id": id,
+                "status": status,
+                "duration_ms": start_time.elapsed().as_millis(),
+            }),
+        );
+
+        if let Some(callback_url) = callback_url {
+            Self::deliver_webhook(&id, &callback_url).await;
+        }
+    }
+
+    /// POSTs the finished job to the `callback_url`, signed with
+    /// `SEARCH_JOB_WEBHOOK_SECRET` (see `signature`). Network errors and 5xx
+    /// responses are retried with exponential backoff.
+    async fn deliver_webhook(id: &str, callback_url: &str) {
+        let (Some(job), Some(secret)) = (Self::job(id), Self::webhook_secret()) else {
+            return;
+        };
+        let body = match serde_json::to_vec(&job) {
+            Ok(body) => body,
+            Err(e) => {
+                log_error(&format!("Failed to serialize search job {}: {}", id, e));
+                return;
+            }
+        };
+
+        let mut delivery = WebhookDelivery {
+            delivered: false,
+            attempts: 0,
+            status: None,
+            error: None,
+        };
+        match Self::webhook_client() {
+            Ok(client) => {
+                for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+                    if attempt > 1 {
+                        tokio::time::sleep(Duration::from_secs(1 << (attempt - 2))).await;
+                    }
+                    delivery.attempts = attempt;
+
+                    let timestamp = Utc::now().timestamp().to_string();
+                    let response = client
+                        .post(callback_url)
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(WEBHOOK_TIMESTAMP_HEADER, &timestamp)
+                        .header(
+                            WEBHOOK_SIGNATURE_HEADER,
+                            format!("sha256={}", Self::signature(&secret, &timestamp, &body)),
+                        )
+                        .body(body.clone())
+                        .send()
+                        .await;
+
+                    match response {
+                        Ok(response) => {
+                            let status = response.status();
+                            delivery.status = Some(status.as_u16());
+                            if status.is_success() {
+                                delivery.delivered = true;
+                                delivery.error = None;
+                                break;
+                            }
+                            delivery.error = Some(format!("callback returned {}", status));
+                            if !status.is_server_error() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            delivery.status = None;
+                            delivery.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            Err(e) => delivery.error = Some(e.to_string()),
+        }
+
+        log_event(
+            "search_job_webhook",
+            json!({
+                "job_id": id,
+                "delivered": delivery.delivered,
+                "attempts": delivery.attempts,
+                "status": delivery.status,
+                "error": delivery.error,
+            }),
+        );
+        Self::update(id, |job| job.webhook = Some(delivery));
+    }
+
+    /// Hex HMAC-SHA256 of `<timestamp>.<body>`, sent as `sha256=<signature>`
+    /// so receivers can check the payload and reject replays.
+    pub fn signature(secret: &str, timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Callbacks go through the same SSRF checks as scraping, without redirects.
+    fn webhook_client() -> reqwest::Result<Client> {
+        Client::builder()
+            .dns_resolver(Arc::new(SsrfGuard))
+            .redirect(Policy::none())
+            .timeout(Duration::from_millis(WEBHOOK_TIMEOUT_MS))
+            .build()
+    }
+
+    fn validate_callback_url(callback_url: &str) -> Result<(), SearchJobError> {
+        let url = Url::parse(callback_url)
+            .map_err(|e| SearchJobError::InvalidCallbackUrl(e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(SearchJobError::InvalidCallbackUrl(
+                "only http and https URLs are supported".to_string(),
+            ));
+        }
+        SsrfGuard::check_ip_literal(&url)
+            .map_err(|blocked| SearchJobError::InvalidCallbackUrl(blocked.to_string()))
+    }
+
+    fn webhook_secret() -> Option<String> {
+        std::env::var("SEARCH_JOB_WEBHOOK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+    }
+
+    /// Runs the search pipeline for a job once it holds its concurrency permit.
+    async fn search(
+        id: &str,
+        caller: &JobCaller,
+        mut search: SearchRequest,
+    ) -> (JobStatus, Option<Value>, Option<Value>) {
+        Self::update(id, |job| {
+            job.status = JobStatus::Running;
+            job.started_at = Some(Self::now());
+        });
+
+        search.response_format = Some(ResponseFormat::Json);
+        let job_id = id.to_string();
+        let outcome = StageTiming::report_to(
+            move |stage, duration| Self::record_stage(&job_id, stage, duration),
+            SearchPipeline::search(&search, &caller.domain_policy, &caller.model_access),
+        )
+        .await;
+
+        match outcome {
+            Ok(response) => {
+                let succeeded = response.status().is_success();
+                let body = match to_bytes(response.into_body()).await {
+                    Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+                        Value::String(String::from_utf8_lossy(&bytes).into_owned())
+                    }),
+                    Err(e) => json!({ "error": "search_failed", "message": e.to_string() }),
+                };
+                if succeeded {
+                    (JobStatus::Succeeded, Some(body), None)
+                } else {
+                    (JobStatus::Failed, None, Some(body))
+                }
+            }
+            Err(e) => (
+                JobStatus::Failed,
+                None,
+                Some(json!({ "error": "search_failed", "message": e.to_string() })),
+            ),
+        }
+    }
+
+    fn record_stage(id: &str, stage: &str, duration: Duration) {
+        Self::update(id, |job| {
+            job.stages.push(StageProgress {
+                stage: stage.to_string(),
+                duration_ms: duration.as_millis(),
+            })
+        });
+    }
+
+    fn job(id: &str) -> Option<SearchJob> {
+        let jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+        jobs.get(id).map(|stored| stored.job.clone())
+    }
+
+    fn update(id: &str, apply: impl FnOnce(&mut SearchJob)) {
+        let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(stored) = jobs.get_mut(id) {
+            apply(&mut stored.job);
+        }
+    }
+
+    fn now() -> String {
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    fn env_usize(name: &str, default: usize) -> usize {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default)
+    }
+}
@@ -0,0 +1,833 @@
+use actix_web::HttpResponse;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::json;
+use std::{error::Error, time::Instant};
+use tracing::Instrument;
+
+use crate::constants::config::{
+    CUSTOM_FORMATTING_PROMPT, GEMINI_MODEL_FLASH, GEMINI_MODEL_PRO, GROUNDED_SEARCH_PROMPT,
+    MOST_RELEVANT_CONTENT_PROMPT, RESEARCH_FORMATTING_PROMPT, RESEARCH_MAX_ITERATIONS,
+    RESEARCH_REPORT_PROMPT, RESEARCH_REVIEW_CONTENT_CHARS, RESEARCH_REVIEW_PROMPT,
+    SEARCH_QUERY_OPTIMISATION_PROMPT,
+};
+use crate::constants::utility::{log_error, log_event, log_query};
+use crate::models::google_ai_models::{AiCompletionRequest, GoogleAiCompletionError};
+use crate::models::google_search_models::{
+    CitationReport, ContextTrim, DomainPolicy, ResponseFormat, SearchAnswerResponse, SearchMode,
+    SearchRequest, SearchResponse, SearchResult, SummarizeRequest,
+};
+use crate::models::research_models::{ResearchHop, ResearchReview, ResearchStopReason};
+use crate::models::usage_models::SearchUsage;
+use crate::services::citation_verification::CitationVerification;
+use crate::services::context_trimming::ContextTrimming;
+use crate::services::domain_filtering::DomainFiltering;
+use crate::services::fixtures::{FixtureUpstream, Fixtures};
+use crate::services::google_ai::GoogleAi;
+use crate::services::metrics::Metrics;
+use crate::services::model_registry::{ModelAccess, ModelCapability, ModelRegistry};
+use crate::services::pricing::Pricing;
+use crate::services::stage_timing::StageTiming;
+use crate::services::upstreams::{Upstream, Upstreams};
+use crate::services::web_scraping::WebScraping;
+
+/// The search, summarize and research pipelines, shared by the route handlers
+/// and search jobs. The caller's domain policy and model access are resolved
+/// from the request beforehand.
+pub struct SearchPipeline;
+
+impl SearchPipeline {
+    /// The search pipeline behind `/api/search` and search jobs: optimise the
+    /// query, search, scrape and answer, or answer with Gemini's Google Search
+    /// tool in grounded mode, or research in rounds in research mode.
+    pub async fn search(
+        body: &SearchRequest,
+        domain_policy: &DomainPolicy,
+        model_access: &ModelAccess,
+    ) -> Result<HttpResponse, Box<dyn Error>> {
+        let start_time = Instant::now();
+        let mut usage = SearchUsage::default();
+
+        let query = body.query.clone();
+
+        if body.mode.unwrap_or_default() == SearchMode::Grounded {
+            let answer_model = body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string());
+            if let Some(response) =
+                Self::model_unavailable(&answer_model, Some(ModelCapability::Tools), model_access)
+            {
+                return Ok(response);
+            }
+            let prompt = GROUNDED_SEARCH_PROMPT.to_string()
+                + &body
+                    .custom_instructions
+                    .clone()
+                    .unwrap_or(CUSTOM_FORMATTING_PROMPT.to_string())
+                + "\n\nQuery:\n"
+                + &query;
+            if let Some(response) = Self::max_cost_exceeded(
+                body,
+                &usage,
+                &answer_model,
+                &prompt,
+                Pricing::grounded_query_cost(),
+            ) {
+                return Ok(response);
+            }
+
+            let answer_start_time = Instant::now();
+            let grounded_response =
+                GoogleAi::grounded_completion(&prompt, &answer_model, model_access)
+                    .instrument(tracing::info_span!("answer", model = %answer_model))
+                    .await?;
+            StageTiming::observe("answer", answer_start_time.elapsed());
+            usage.record_completion(&grounded_response);
+            usage.record_grounded_query();
+
+            let end_time = Instant::now();
+            let duration = end_time.duration_since(start_time);
+            tracing::info!("Googlexity Grounded Search time taken: {:?}", duration);
+
+            return Ok(Self::search_answer_response(
+                body,
+                SearchAnswerResponse {
+                    answer: grounded_response.text,
+                    model: grounded_response.model,
+                    usage,
+                    context_trim: None,
+                    verification: None,
+                    research_trace: None,
+                },
+            ));
+        }
+
+        let answer_model = body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string());
+        if let Some(response) = Self::model_unavailable(&answer_model, None, model_access) {
+            return Ok(response);
+        }
+
+        let split_search_queries = Self::optimised_search_queries(body, &mut usage).await?;
+
+        if body.mode.unwrap_or_default() == SearchMode::Research {
+            return Self::research(
+                body,
+                domain_policy,
+                model_access,
+                split_search_queries,
+                usage,
+                start_time,
+            )
+            .await;
+        }
+
+        let search_results =
+            Self::search_results(body, domain_policy, split_search_queries, false, &mut usage)
+                .await?;
+
+        let (prompt, updated_search_results, context_trim) =
+            Self::answer_prompt(body, search_results, &answer_model).await?;
+
+        let ai_request = AiCompletionRequest {
+            query: prompt,
+            model: Some(answer_model.clone()),
+        };
+
+        let ai_request_length = ai_request.query.len();
+        tracing::info!("AI request length: {}", ai_request_length);
+
+        if let Some(response) =
+            Self::max_cost_exceeded(body, &usage, &answer_model, &ai_request.query, 0.0)
+        {
+            return Ok(response);
+        }
+
+        let answer_start_time = Instant::now();
+        let most_relevant_search_results =
+            GoogleAi::completion(&ai_request.query, &answer_model, model_access)
+                .instrument(tracing::info_span!("answer", model = %answer_model))
+                .await?;
+        StageTiming::observe("answer", answer_start_time.elapsed());
+        usage.record_completion(&most_relevant_search_results);
+
+        let verification = Self::verify_citations(
+            body,
+            &most_relevant_search_results.text,
+            &updated_search_results,
+        );
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Googlexity Search time taken: {:?}", duration);
+
+        Ok(Self::search_answer_response(
+            body,
+            SearchAnswerResponse {
+                answer: most_relevant_search_results.text,
+                model: most_relevant_search_results.model,
+                usage,
+                context_trim,
+                verification,
+                research_trace: None,
+            },
+        ))
+    }
+
+    /// Answers a question (or summarises) from the given URLs, skipping Custom Search.
+    pub async fn summarize(
+        body: &SummarizeRequest,
+        domain_policy: &DomainPolicy,
+        model_access: &ModelAccess,
+    ) -> Result<HttpResponse, Box<dyn Error>> {
+        let start_time = Instant::now();
+        let mut usage = SearchUsage::default();
+
+        if body.urls.is_empty() {
+            return Ok(
+                HttpResponse::BadRequest().json(json!({ "error": "urls must not be empty" }))
+            );
+        }
+
+        let answer_model = body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string());
+        if let Some(response) = Self::model_unavailable(&answer_model, None, model_access) {
+            return Ok(response);
+        }
+
+        let search_request = body.search_request();
+        let pages = DomainFiltering::filter(
+            body.urls
+                .iter()
+                .map(|url| SearchResult::from_link(url))
+                .collect(),
+            domain_policy,
+        );
+        let mut pages = WebScraping::retrieve_all_website_text_content(pages).await;
+        let prompt_prefix = Self::answer_prompt_prefix(&search_request);
+        let context_trim =
+            Self::fit_context_window(&prompt_prefix, &mut pages, &answer_model).await?;
+
+        let prompt = prompt_prefix + &serde_json::to_string(&pages)?;
+        if let Some(response) =
+            Self::max_cost_exceeded(&search_request, &usage, &answer_model, &prompt, 0.0)
+        {
+            return Ok(response);
+        }
+
+        let answer_start_time = Instant::now();
+        let summary = GoogleAi::completion(&prompt, &answer_model, model_access)
+            .instrument(tracing::info_span!("answer", model = %answer_model))
+            .await?;
+        StageTiming::observe("answer", answer_start_time.elapsed());
+        usage.record_completion(&summary);
+
+        let verification = Self::verify_citations(&search_request, &summary.text, &pages);
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Googlexity Summarize time taken: {:?}", duration);
+
+        Ok(Self::search_answer_response(
+            &search_request,
+            SearchAnswerResponse {
+                answer: summary.text,
+                model: summary.model,
+                usage,
+                context_trim,
+                verification,
+                research_trace: None,
+            },
+        ))
+    }
+
+    /// Runs the search queries through Custom Search (or uses the mock results),
+    /// keeping the results allowed by the domain policy, ranked and capped at `max_results`.
+    pub async fn search_results(
+        body: &SearchRequest,
+        domain_policy: &DomainPolicy,
+        queries: Vec<String>,
+        mock: bool,
+        usage: &mut SearchUsage,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        let mut search_results: Vec<SearchResult> = Vec::new();
+        if mock {
+            search_results = WebScraping::get_mock_search_results()?;
+        } else {
+            for query in queries {
+                let search_items = Self::google_search(&query, domain_policy).await?;
+                usage.record_search_query();
+                search_results.extend(search_items);
+            }
+        }
+        search_results =
+            DomainFiltering::rank(DomainFiltering::filter(search_results, domain_policy));
+
+        if let Some(max_results) = body.max_results {
+            if let Ok(max) = usize::try_from(max_results) {
+                search_results = search_results.into_iter().take(max).collect();
+            }
+        }
+
+        Ok(search_results)
+    }
+
+    /// Scrapes the results for a depthful search and builds the final answer prompt,
+    /// trimmed to fit the model's context window. Returns the prompt along with the
+    /// results it contains and what was trimmed.
+    pub async fn answer_prompt(
+        body: &SearchRequest,
+        search_results: Vec<SearchResult>,
+        model: &str,
+    ) -> Result<(String, Vec<SearchResult>, Option<ContextTrim>), Box<dyn Error>> {
+        let search_results_text = serde_json::to_string(&search_results)?;
+        tracing::info!(
+            "Initial search results content length: {}",
+            search_results_text.len()
+        );
+
+        let mut updated_search_results = if body.depthfull_search.unwrap_or(false) {
+            WebScraping::retrieve_all_website_text_content(search_results).await
+        } else {
+            search_results
+        };
+
+        let prompt_prefix = Self::answer_prompt_prefix(body);
+        let context_trim =
+            Self::fit_context_window(&prompt_prefix, &mut updated_search_results, model).await?;
+
+        let stringified_search_results = serde_json::to_string(&updated_search_results)?;
+        if body.depthfull_search.unwrap_or(false) {
+            let updated_search_results_length = stringified_search_results.len();
+            tracing::info!(
+                "Updated search results content length: {}",
+                updated_search_results_length
+            );
+        }
+
+        Ok((
+            prompt_prefix + &stringified_search_results,
+            updated_search_results,
+            context_trim,
+        ))
+    }
+
+    /// The answer prompt up to the search results, which are appended as JSON.
+    fn answer_prompt_prefix(body: &SearchRequest) -> String {
+        MOST_RELEVANT_CONTENT_PROMPT.to_string()
+            + &body
+                .custom_instructions
+                .clone()
+                .unwrap_or(CUSTOM_FORMATTING_PROMPT.to_string())
+            + "\n\nQuery:\n"
+            + &body.query
+            + "\n\nSearch Results:\n"
+    }
+
+    /// Optimises the query into one or more search queries (unless `optimize_query`
+    /// is false), capped at `max_optimizations`.
+    #[tracing::instrument(name = "optimisation", skip_all)]
+    pub async fn optimised_search_queries(
+        body: &SearchRequest,
+        usage: &mut SearchUsage,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let optimised_search_response = if body.optimize_query.unwrap_or(true) {
+            let optimisation_start_time = Instant::now();
+            let optimised_completion = GoogleAi::completion(
+                &(SEARCH_QUERY_OPTIMISATION_PROMPT.to_string() + &body.query),
+                GEMINI_MODEL_FLASH,
+                &ModelAccess::server(),
+            )
+            .await?;
+            StageTiming::observe("optimisation", optimisation_start_time.elapsed());
+            usage.record_completion(&optimised_completion);
+            optimised_completion.text
+        } else {
+            body.query.clone()
+        };
+
+        log_query(&format!(
+            "Optimised search response: {}",
+            optimised_search_response
+        ));
+
+        let mut split_search_queries: Vec<String> = if optimised_search_response.contains(";") {
+            optimised_search_response
+                .split(';')
+                .map(|s| s.replace("\n", ""))
+                .collect()
+        } else {
+            vec![optimised_search_response]
+        };
+
+        if let Some(max_optimizations) = body.max_optimizations {
+            if let Ok(max) = usize::try_from(max_optimizations) {
+                split_search_queries = split_search_queries.into_iter().take(max).collect();
+            }
+        }
+
+        log_query(&format!("Split search queries: {:?}", split_search_queries));
+
+        Ok(split_search_queries)
+    }
+
+    /// Research mode: search and scrape in rounds, asking Gemini after each round
+    /// whether the evidence is enough or which follow-up queries to run, then write
+    /// a long-form report from everything gathered.
+    async fn research(
+        body: &SearchRequest,
+        domain_policy: &DomainPolicy,
+        model_access: &ModelAccess,
+        initial_queries: Vec<String>,
+        mut usage: SearchUsage,
+        start_time: Instant,
+    ) -> Result<HttpResponse, Box<dyn Error>> {
+        let max_iterations = body
+            .max_iterations
+            .and_then(|max| usize::try_from(max).ok())
+            .unwrap_or(RESEARCH_MAX_ITERATIONS)
+            .max(1);
+
+        let mut search_results: Vec<SearchResult> = Vec::new();
+        let mut searched_queries: Vec<String> = Vec::new();
+        let mut research_trace: Vec<ResearchHop> = Vec::new();
+        let mut queries = initial_queries;
+
+        for iteration in 1..=max_iterations {
+            let hop_start_time = Instant::now();
+
+            let mut hop_results: Vec<SearchResult> = Vec::new();
+            for query in &queries {
+                if searched_queries.contains(query) {
+                    continue;
+                }
+                searched_queries.push(query.clone());
+
+                let search_items = Self::google_search(query, domain_policy).await?;
+                usage.record_search_query();
+                for item in DomainFiltering::filter(search_items, domain_policy) {
+                    let already_gathered = search_results
+                        .iter()
+                        .chain(hop_results.iter())
+                        .any(|gathered| gathered.link == item.link);
+                    if !already_gathered {
+                        hop_results.push(item);
+                    }
+                }
+            }
+
+            hop_results = DomainFiltering::rank(hop_results);
+            if let Some(max_results) = body.max_results {
+                if let Ok(max) = usize::try_from(max_results) {
+                    hop_results = hop_results.into_iter().take(max).collect();
+                }
+            }
+            let new_results = hop_results.len();
+
+            if body.depthfull_search.unwrap_or(true) {
+                hop_results = WebScraping::retrieve_all_website_text_content(hop_results).await;
+            }
+            search_results.extend(hop_results);
+
+            let mut hop = ResearchHop {
+                iteration,
+                queries: queries.clone(),
+                new_results,
+                confident: false,
+                reasoning: None,
+                follow_up_queries: Vec::new(),
+                stop_reason: None,
+                duration_ms: 0,
+            };
+
+            let evidence: Vec<serde_json::Value> = search_results
+                .iter()
+                .map(|item| {
+                    json!({
+                        "title": item.title,
+                        "link": item.link,
+                        "snippet": item.snippet,
+                        "website_text_content": item.website_text_content.as_ref().map(|content| {
+                            content
+                                .chars()
+                                .take(RESEARCH_REVIEW_CONTENT_CHARS)
+                                .collect::<String>()
+                        }),
+                    })
+                })
+                .collect();
+            let review_prompt = RESEARCH_REVIEW_PROMPT.to_string()
+                + "\n\nQuery:\n"
+                + &body.query
+                + "\n\nSearched Queries:\n"
+                + &searched_queries.join("\n")
+                + "\n\nEvidence:\n"
+                + &serde_json::to_string(&evidence)?;
+
+            let over_budget = body.max_cost.is_some_and(|max_cost| {
+                usage.estimated_cost_usd
+                    + Pricing::estimate_completion_cost(GEMINI_MODEL_FLASH, &review_prompt)
+                    > max_cost
+            });
+            if iteration == max_iterations || over_budget {
+                hop.stop_reason = Some(if over_budget {
+                    ResearchStopReason::MaxCost
+                } else {
+                    ResearchStopReason::MaxIterations
+                });
+                hop.duration_ms = hop_start_time.elapsed().as_millis();
+                research_trace.push(hop);
+                break;
+            }
+
+            let review_start_time = Instant::now();
+            let review_completion = GoogleAi::json_completion(
+                &review_prompt,
+                GEMINI_MODEL_FLASH,
+                &ModelAccess::server(),
+            )
+            .await?;
+            StageTiming::observe("research_review", review_start_time.elapsed());
+            usage.record_completion(&review_completion);
+            let review = match serde_json::from_str::<ResearchReview>(&review_completion.text) {
+                Ok(review) => review,
+                Err(e) => {
+                    log_error(&format!("Failed to parse research review: {}", e));
+                    ResearchReview::default()
+                }
+            };
+
+            let mut follow_up_queries: Vec<String> = review
+                .follow_up_queries
+                .into_iter()
+                .map(|query| query.trim().to_string())
+                .filter(|query| !query.is_empty() && !searched_queries.contains(query))
+                .collect();
+            if let Some(max_optimizations) = body.max_optimizations {
+                if let Ok(max) = usize::try_from(max_optimizations) {
+                    follow_up_queries.truncate(max);
+                }
+            }
+
+            hop.confident = review.confident;
+            hop.reasoning = review.reasoning;
+            hop.follow_up_queries = follow_up_queries.clone();
+            if review.confident {
+                hop.stop_reason = Some(ResearchStopReason::Confident);
+            } else if follow_up_queries.is_empty() {
+                hop.stop_reason = Some(ResearchStopReason::NoFollowUpQueries);
+            }
+            hop.duration_ms = hop_start_time.elapsed().as_millis();
+
+            log_query(&format!("Research hop: {:?}", hop));
+
+            let stop = hop.stop_reason.is_some();
+            research_trace.push(hop);
+            if stop {
+                break;
+            }
+            queries = follow_up_queries;
+        }
+
+        let mut search_results = DomainFiltering::rank(search_results);
+        let answer_model = body.model.clone().unwrap_or(GEMINI_MODEL_PRO.to_string());
+        let trace_summary: Vec<serde_json::Value> = research_trace
+            .iter()
+            .map(|hop| json!({ "queries": hop.queries, "reasoning": hop.reasoning }))
+            .collect();
+        let prompt_prefix = RESEARCH_REPORT_PROMPT.to_string()
+            + &body
+                .custom_instructions
+                .clone()
+                .unwrap_or(RESEARCH_FORMATTING_PROMPT.to_string())
+            + "\n\nQuery:\n"
+            + &body.query
+            + "\n\nResearch Trace:\n"
+            + &serde_json::to_string(&trace_summary)?
+            + "\n\nSearch Results:\n";
+
+        let context_trim =
+            Self::fit_context_window(&prompt_prefix, &mut search_results, &answer_model).await?;
+        let report_prompt = prompt_prefix + &serde_json::to_string(&search_results)?;
+
+        if let Some(response) =
+            Self::max_cost_exceeded(body, &usage, &answer_model, &report_prompt, 0.0)
+        {
+            return Ok(response);
+        }
+
+        let answer_start_time = Instant::now();
+        let report = GoogleAi::completion(&report_prompt, &answer_model, model_access)
+            .instrument(tracing::info_span!("answer", model = %answer_model))
+            .await?;
+        StageTiming::observe("answer", answer_start_time.elapsed());
+        usage.record_completion(&report);
+
+        let verification = Self::verify_citations(body, &report.text, &search_results);
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Googlexity Research time taken: {:?}", duration);
+
+        Ok(Self::search_answer_response(
+            body,
+            SearchAnswerResponse {
+                answer: report.text,
+                model: report.model,
+                usage,
+                context_trim,
+                verification,
+                research_trace: Some(research_trace),
+            },
+        ))
+    }
+
+    /// A 402 response if the cost so far plus the estimated cost of answering
+    /// `prompt` (including a full-length answer) and any `fees` charged on top
+    /// of the tokens, like grounding's per-query price, would exceed `max_cost`.
+    fn max_cost_exceeded(
+        body: &SearchRequest,
+        usage: &SearchUsage,
+        model: &str,
+        prompt: &str,
+        fees: f64,
+    ) -> Option<HttpResponse> {
+        let max_cost = body.max_cost?;
+        let estimated_cost =
+            usage.estimated_cost_usd + Pricing::estimate_completion_cost(model, prompt) + fees;
+        if estimated_cost <= max_cost {
+            return None;
+        }
+
+        log_event(
+            "search_aborted",
+            json!({
+                "query": body.query,
+                "reason": "max_cost",
+                "max_cost": max_cost,
+                "estimated_cost_usd": estimated_cost,
+                "usage": usage,
+            }),
+        );
+
+        Some(HttpResponse::PaymentRequired().json(json!({
+            "error": format!(
+                "Estimated cost ${:.4} exceeds max_cost ${:.4}",
+                estimated_cost, max_cost
+            ),
+            "usage": usage,
+        })))
+    }
+
+    /// A 403 if neither `model` nor any of its fallbacks may be used by the caller,
+    /// or a 400 if they're all deprecated or missing `capability`.
+    pub fn model_unavailable(
+        model: &str,
+        capability: Option<ModelCapability>,
+        access: &ModelAccess,
+    ) -> Option<HttpResponse> {
+        if !ModelRegistry::fallback_chain(model, capability, access).is_empty() {
+            return None;
+        }
+
+        let message = GoogleAiCompletionError::NoAvailableModel {
+            requested: model.to_string(),
+        }
+        .to_string();
+        Some(if access.allows(model) {
+            HttpResponse::BadRequest()
+                .json(json!({ "error": "model_unavailable", "message": message }))
+        } else {
+            HttpResponse::Forbidden()
+                .json(json!({ "error": "model_not_allowed", "message": message }))
+        })
+    }
+
+    fn verify_citations(
+        body: &SearchRequest,
+        answer: &str,
+        search_results: &[SearchResult],
+    ) -> Option<CitationReport> {
+        if !body.verify_citations.unwrap_or(false) {
+            return None;
+        }
+
+        let verification = CitationVerification::verify(answer, search_results);
+        log_event(
+            "citation_verification",
+            json!({
+                "query": body.query,
+                "support_score": verification.support_score,
+                "claims": verification.claims.len(),
+                "unsupported_claims": verification.unsupported_claims,
+                "unknown_sources": verification.unknown_sources,
+            }),
+        );
+
+        Some(verification)
+    }
+
+    /// Trims `search_results` until the answer prompt fits the model's context window,
+    /// returning what was removed (if anything). Only prompts that might not fit
+    /// are measured with `countTokens`.
+    async fn fit_context_window(
+        prompt_prefix: &str,
+        search_results: &mut Vec<SearchResult>,
+        model: &str,
+    ) -> Result<Option<ContextTrim>, Box<dyn Error>> {
+        let start_time = Instant::now();
+        let token_limit = ContextTrimming::prompt_token_limit(model);
+        let prompt = prompt_prefix.to_string() + &serde_json::to_string(search_results)?;
+        if ContextTrimming::clearly_fits(&prompt, token_limit) {
+            StageTiming::observe("context_trimming", start_time.elapsed());
+            return Ok(None);
+        }
+
+        let mut tokens = GoogleAi::count_tokens(model, &prompt).await;
+
+        if tokens <= token_limit {
+            StageTiming::observe("context_trimming", start_time.elapsed());
+            return Ok(None);
+        }
+
+        let mut context_trim = ContextTrim {
+            original_tokens: tokens,
+            token_limit,
+            ..Default::default()
+        };
+
+        // The trimming estimate is recalibrated against a real count after each pass.
+        for _ in 0..3 {
+            ContextTrimming::trim(
+                prompt_prefix,
+                search_results,
+                tokens,
+                token_limit,
+                &mut context_trim,
+            );
+            tokens = GoogleAi::count_tokens(
+                model,
+                &(prompt_prefix.to_string() + &serde_json::to_string(search_results)?),
+            )
+            .await;
+            if tokens <= token_limit || search_results.is_empty() {
+                break;
+            }
+        }
+        context_trim.final_tokens = tokens;
+        StageTiming::observe("context_trimming", start_time.elapsed());
+
+        log_event(
+            "context_trimmed",
+            json!({ "model": model, "trim": context_trim }),
+        );
+
+        Ok(Some(context_trim))
+    }
+
+    /// Logs the usage totals and builds the response in the requested format.
+    fn search_answer_response(
+        body: &SearchRequest,
+        response: SearchAnswerResponse,
+    ) -> HttpResponse {
+        log_event(
+            "search_usage",
+            json!({
+                "query": body.query,
+                "mode": body.mode.unwrap_or_default(),
+                "usage": response.usage,
+            }),
+        );
+
+        match body.response_format.unwrap_or_default() {
+            ResponseFormat::Json => HttpResponse::Ok().json(response),
+            ResponseFormat::Markdown => {
+                let usage = &response.usage;
+                let mut http_response = HttpResponse::Ok();
+                http_response
+                    .insert_header(("x-googlexity-model", response.model.as_str()))
+                    .insert_header(("x-googlexity-prompt-tokens", usage.prompt_tokens))
+                    .insert_header(("x-googlexity-candidates-tokens", usage.candidates_tokens))
+                    .insert_header(("x-googlexity-search-queries", usage.search_queries))
+                    .insert_header((
+                        "x-googlexity-cost-usd",
+                        format!("{:.6}", usage.estimated_cost_usd),
+                    ));
+                if let Some(context_trim) = &response.context_trim {
+                    http_response
+                        .insert_header((
+                            "x-googlexity-trimmed-content",
+                            context_trim.dropped_website_content.len(),
+                        ))
+                        .insert_header((
+                            "x-googlexity-trimmed-results",
+                            context_trim.dropped_results.len(),
+                        ));
+                }
+                if let Some(verification) = &response.verification {
+                    http_response
+                        .insert_header((
+                            "x-googlexity-support-score",
+                            format!("{:.3}", verification.support_score),
+                        ))
+                        .insert_header((
+                            "x-googlexity-unsupported-claims",
+                            verification.unsupported_claims,
+                        ))
+                        .insert_header((
+                            "x-googlexity-unknown-sources",
+                            verification.unknown_sources.len(),
+                        ));
+                }
+                http_response.body(response.answer)
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(domain_policy))]
+    async fn google_search(
+        query: &str,
+        domain_policy: &DomainPolicy,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        let search_api_key = std::env::var("SEARCH_API_KEY").unwrap();
+        let search_engine_id = std::env::var("SEARCH_ENGINE_ID").unwrap();
+        let start_time = Instant::now();
+
+        let client = Upstreams::client(Upstream::CustomSearch);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Content-Type".parse::<HeaderName>().unwrap(),
+            "application/json".parse::<HeaderValue>().unwrap(),
+        );
+        let google_search_response = match Fixtures::send(
+            FixtureUpstream::GoogleSearch,
+            client
+                .get(Upstreams::custom_search_url())
+                .query(&[("key", search_api_key), ("cx", search_engine_id)])
+                .query(&DomainFiltering::site_search_params(query, domain_policy))
+                .headers(headers),
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                Metrics::record_custom_search("error");
+                log_error(&format!("Request failed: {}", e));
+                return Err(format!("Request failed: {}", e).into());
+            }
+        };
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Google Search time taken: {:?}", duration);
+        StageTiming::observe("custom_search", duration);
+
+        if !google_search_response.status().is_success() {
+            Metrics::record_custom_search(google_search_response.status().as_str());
+            return Err(format!("HTTP error! status: {}", google_search_response.status()).into());
+        }
+        Metrics::record_custom_search("success");
+
+        let google_search_response_json: SearchResponse =
+            google_search_response.json::<SearchResponse>().await?;
+
+        let items = google_search_response_json.items;
+
+        Ok(items)
+    }
+}
@@ -0,0 +1,317 @@
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use scraper::Html;
+use serde_json::Value;
+use std::error::Error;
+use url::Url;
+
+use crate::constants::config::{
+    ALLOWED_CONTENT_TYPES, JSON_CONTENT_TYPES, REDDIT_MAX_COMMENTS, SITE_EXTRACTOR_DOMAINS,
+};
+use crate::services::domain_filtering::DomainFiltering;
+use crate::services::fixtures::{FixtureUpstream, Fixtures};
+use crate::services::web_scraping::WebScraping;
+
+/// Site-specific extraction for social and video sites whose HTML is of little
+/// use, used by `WebScraping` in place of generic HTML scraping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SiteExtractor {
+    /// The thread's `.json` endpoint: post and top comments
+    Reddit,
+    /// oEmbed metadata and the caption track transcript
+    YouTube,
+    /// oEmbed post text
+    X,
+    /// oEmbed metadata, requires `INSTAGRAM_OEMBED_TOKEN`
+    Instagram,
+    /// oEmbed metadata
+    TikTok,
+}
+
+impl SiteExtractor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Reddit => "reddit",
+            Self::YouTube => "youtube",
+            Self::X => "x",
+            Self::Instagram => "instagram",
+            Self::TikTok => "tiktok",
+        }
+    }
+
+    const ALL: [Self; 5] = [
+        Self::Reddit,
+        Self::YouTube,
+        Self::X,
+        Self::Instagram,
+        Self::TikTok,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|extractor| extractor.name() == name)
+    }
+
+    /// Extractors enabled with the `SITE_EXTRACTORS` env variable (comma
+    /// separated names, defaults to all).
+    pub fn enabled() -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|extractor| extractor.is_enabled())
+            .collect()
+    }
+
+    /// Instagram is also left out while `INSTAGRAM_OEMBED_TOKEN` isn't set, so
+    /// its links fall back to generic scraping.
+    fn is_enabled(&self) -> bool {
+        if *self == Self::Instagram && std::env::var("INSTAGRAM_OEMBED_TOKEN").is_err() {
+            return false;
+        }
+        match std::env::var("SITE_EXTRACTORS") {
+            Ok(names) if !names.trim().is_empty() => names
+                .split(',')
+                .any(|name| name.trim().eq_ignore_ascii_case(self.name())),
+            _ => true,
+        }
+    }
+
+    /// The enabled extractor for a link, if any.
+    pub fn for_link(link: &str) -> Option<Self> {
+        let host = DomainFiltering::host(link)?;
+        let extractor = SITE_EXTRACTOR_DOMAINS
+            .iter()
+            .find(|(domain, _)| DomainFiltering::matches(&host, domain))
+            .and_then(|(_, name)| Self::from_name(name))?;
+
+        extractor.is_enabled().then_some(extractor)
+    }
+
+    pub async fn extract(
+        &self,
+        url: &str,
+        client: &Client,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        match self {
+            Self::Reddit => Self::extract_reddit(url, client).await,
+            Self::YouTube => Self::extract_youtube(url, client).await,
+            Self::X => {
+                Self::extract_oembed("https://publish.twitter.com/oembed", url, client, &[]).await
+            }
+            Self::Instagram => {
+                let access_token = std::env::var("INSTAGRAM_OEMBED_TOKEN")
+                    .map_err(|_| "INSTAGRAM_OEMBED_TOKEN is not set")?;
+                Self::extract_oembed(
+                    "https://graph.facebook.com/v18.0/instagram_oembed",
+                    url,
+                    client,
+                    &[("access_token", access_token.as_str())],
+                )
+                .await
+            }
+            Self::TikTok => {
+                Self::extract_oembed("https://www.tiktok.com/oembed", url, client, &[]).await
+            }
+        }
+    }
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "User-Agent".parse::<HeaderName>().unwrap(),
+            "Googlexity/0.1.0".parse::<HeaderValue>().unwrap(),
+        );
+        headers
+    }
+
+    async fn get_json(
+        url: &str,
+        query: &[(&str, &str)],
+        client: &Client,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let response = Fixtures::send(
+            FixtureUpstream::Pages,
+            client.get(url).query(query).headers(Self::headers()),
+        )
+        .await?;
+        let (_, body) = WebScraping::read_body(url, response, JSON_CONTENT_TYPES).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    async fn get_text(url: &str, client: &Client) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let response = Fixtures::send(
+            FixtureUpstream::Pages,
+            client.get(url).headers(Self::headers()),
+        )
+        .await?;
+        let (_, body) = WebScraping::read_body(url, response, ALLOWED_CONTENT_TYPES).await?;
+        Ok(body)
+    }
+
+    /// Reads the thread through Reddit's JSON API (`<thread url>.json`), keeping
+    /// the post and its highest-scoring comments.
+    async fn extract_reddit(
+        url: &str,
+        client: &Client,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut parsed_url = Url::parse(url)?;
+        parsed_url.set_query(None);
+        parsed_url.set_fragment(None);
+        let json_url = format!("{}.json", parsed_url.as_str().trim_end_matches('/'));
+
+        let listing = Self::get_json(&json_url, &[("limit", "100")], client).await?;
+        let listings: Vec<&Value> = match &listing {
+            Value::Array(listings) => listings.iter().collect(),
+            listing => vec![listing],
+        };
+
+        let mut posts: Vec<String> = Vec::new();
+        let mut comments: Vec<(i64, String)> = Vec::new();
+        for listing in listings {
+            Self::collect_reddit_things(listing, &mut posts, &mut comments);
+        }
+
+        comments.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        let mut text = posts.join("\n\n");
+        if !comments.is_empty() {
+            text.push_str("\n\nComments:\n");
+            for (score, body) in comments.into_iter().take(REDDIT_MAX_COMMENTS) {
+                text.push_str(&format!("- ({} points) {}\n", score, body));
+            }
+        }
+
+        if text.trim().is_empty() {
+            return Err("No Reddit content found".into());
+        }
+        Ok(text)
+    }
+
+    fn collect_reddit_things(
+        thing: &Value,
+        posts: &mut Vec<String>,
+        comments: &mut Vec<(i64, String)>,
+    ) {
+        let data = &thing["data"];
+        match thing["kind"].as_str() {
+            Some("Listing") => {
+                if let Some(children) = data["children"].as_array() {
+                    for child in children {
+                        Self::collect_reddit_things(child, posts, comments);
+                    }
+                }
+            }
+            Some("t3") => {
+                let mut post = format!(
+                    "{} (r/{}, {} points)",
+                    data["title"].as_str().unwrap_or_default(),
+                    data["subreddit"].as_str().unwrap_or_default(),
+                    data["score"].as_i64().unwrap_or(0)
+                );
+                if let Some(selftext) = data["selftext"].as_str().filter(|text| !text.is_empty()) {
+                    post.push('\n');
+                    post.push_str(selftext);
+                }
+                posts.push(post);
+            }
+            Some("t1") => {
+                if let Some(body) = data["body"].as_str() {
+                    comments.push((data["score"].as_i64().unwrap_or(0), body.to_string()));
+                }
+                if data["replies"].is_object() {
+                    Self::collect_reddit_things(&data["replies"], posts, comments);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// oEmbed metadata plus the transcript from the video's caption track,
+    /// preferring English captions.
+    async fn extract_youtube(
+        url: &str,
+        client: &Client,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut text = Self::extract_oembed("https://www.youtube.com/oembed", url, client, &[])
+            .await
+            .unwrap_or_default();
+
+        let watch_page = Self::get_text(url, client).await?;
+
+        let caption_tracks_regex = Regex::new(r#""captionTracks":(\[.*?\])"#).unwrap();
+        let caption_tracks: Vec<Value> = caption_tracks_regex
+            .captures(&watch_page)
+            .and_then(|captures| serde_json::from_str(&captures[1]).ok())
+            .unwrap_or_default();
+
+        let caption_track = caption_tracks
+            .iter()
+            .find(|track| {
+                track["languageCode"]
+                    .as_str()
+                    .is_some_and(|language| language.starts_with("en"))
+            })
+            .or_else(|| caption_tracks.first());
+
+        if let Some(base_url) = caption_track.and_then(|track| track["baseUrl"].as_str()) {
+            let captions = Self::get_text(base_url, client).await?;
+
+            let caption_text_regex = Regex::new(r"(?s)<text[^>]*>(.*?)</text>").unwrap();
+            let transcript: Vec<String> = caption_text_regex
+                .captures_iter(&captions)
+                .map(|captures| {
+                    Html::parse_fragment(&captures[1])
+                        .root_element()
+                        .text()
+                        .collect::<String>()
+                })
+                .collect();
+
+            if !transcript.is_empty() {
+                text.push_str("\n\nTranscript:\n");
+                text.push_str(&transcript.join(" "));
+            }
+        }
+
+        if text.trim().is_empty() {
+            return Err("No YouTube metadata or transcript found".into());
+        }
+        Ok(text)
+    }
+
+    async fn extract_oembed(
+        endpoint: &str,
+        url: &str,
+        client: &Client,
+        query: &[(&str, &str)],
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut oembed_query = vec![("url", url), ("format", "json")];
+        oembed_query.extend_from_slice(query);
+        let oembed = Self::get_json(endpoint, &oembed_query, client).await?;
+
+        let mut lines: Vec<String> = Vec::new();
+        if let Some(title) = oembed["title"].as_str().filter(|title| !title.is_empty()) {
+            lines.push(format!("Title: {}", title));
+        }
+        if let Some(author_name) = oembed["author_name"].as_str() {
+            lines.push(format!("Author: {}", author_name));
+        }
+        // X embeds the post text in a blockquote.
+        if let Some(html) = oembed["html"].as_str() {
+            let html_text = Html::parse_fragment(html)
+                .root_element()
+                .text()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let html_text = html_text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !html_text.is_empty() {
+                lines.push(format!("Content: {}", html_text));
+            }
+        }
+
+        if lines.is_empty() {
+            return Err("Empty oEmbed response".into());
+        }
+        Ok(lines.join("\n"))
+    }
+}
@@ -0,0 +1,338 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{error::Error, fmt};
+use url::{Host, Url};
+
+use crate::services::domain_filtering::DomainFiltering;
+
+/// A host that resolved only to private, loopback, link-local or otherwise
+/// internal addresses.
+#[derive(Debug)]
+pub struct SsrfBlocked {
+    pub host: String,
+}
+
+impl fmt::Display for SsrfBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} resolves to a blocked internal address", self.host)
+    }
+}
+
+impl Error for SsrfBlocked {}
+
+/// DNS resolver for scraping clients that drops internal addresses after
+/// resolution, so every connection (including each redirect hop) is checked.
+/// Hosts and CIDR ranges in the `SSRF_ALLOWLIST` env variable are exempt.
+pub struct SsrfGuard;
+
+impl Resolve for SsrfGuard {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| Self::is_allowed(&host, addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(Box::new(SsrfBlocked { host }) as Box<dyn Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+impl SsrfGuard {
+    /// Redirect policy that also rejects redirects to internal IP literals,
+    /// which don't go through the resolver.
+    pub fn redirect_policy(max_redirects: usize) -> Policy {
+        Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                return attempt.error("too many redirects");
+            }
+            match Self::check_ip_literal(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(blocked) => attempt.error(blocked),
+            }
+        })
+    }
+
+    /// Rejects URLs whose host is an internal IP literal. Hostnames are checked
+    /// by the resolver when connecting.
+    pub fn check_ip_literal(url: &Url) -> Result<(), SsrfBlocked> {
+        let ip = match url.host() {
+            Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+            _ => return Ok(()),
+        };
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        if Self::is_allowed(&host, ip) {
+            Ok(())
+        } else {
+            Err(SsrfBlocked { host })
+        }
+    }
+
+    /// Resolves the URL's host and rejects it if any address is internal, for
+    /// URLs fetched by something other than a guarded client (e.g. the headless browser).
+    pub async fn check_url(url: &str) -> Result<(), SsrfBlocked> {
+        let parsed_url = Url::parse(url).map_err(|_| SsrfBlocked {
+            host: url.to_string(),
+        })?;
+        Self::check_ip_literal(&parsed_url)?;
+
+        let host = parsed_url.host_str().unwrap_or_default().to_string();
+        if matches!(parsed_url.host(), Some(Host::Domain(_))) {
+            let port = parsed_url.port_or_known_default().unwrap_or(80);
+            let allowed = match tokio::net::lookup_host((host.as_str(), port)).await {
+                Ok(mut addrs) => addrs.all(|addr| Self::is_allowed(&host, addr.ip())),
+                Err(_) => false,
+            };
+            if !allowed {
+                return Err(SsrfBlocked { host });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_allowed(host: &str, ip: IpAddr) -> bool {
+        !Self::is_internal(ip) || Self::is_allowlisted(host, ip)
+    }
+
+    fn is_allowlisted(host: &str, ip: IpAddr) -> bool {
+        Self::allowlist_allows(
+            &std::env::var("SSRF_ALLOWLIST").unwrap_or_default(),
+            host,
+            ip,
+        )
+    }
+
+    /// Whether an entry of the comma separated `allowlist` (CIDR ranges,
+    /// addresses or host patterns) matches the host or its address.
+    fn allowlist_allows(allowlist: &str, host: &str, ip: IpAddr) -> bool {
+        allowlist
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .any(|entry| match Self::parse_cidr(entry) {
+                Some((network, prefix)) => Self::in_network(ip, network, prefix),
+                None => DomainFiltering::matches(&host.to_lowercase(), entry),
+            })
+    }
+
+    /// Private, loopback, link-local (including the 169.254.169.254 metadata
+    /// service), carrier-grade NAT, multicast and reserved ranges.
+    pub fn is_internal(ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => Self::is_internal_v4(ip),
+            IpAddr::V6(ip) => {
+                if let Some(mapped) = ip.to_ipv4_mapped() {
+                    return Self::is_internal_v4(mapped);
+                }
+                let segments = ip.segments();
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (segments[0] & 0xfe00) == 0xfc00 // unique local fc00::/7
+                    || (segments[0] & 0xffc0) == 0xfe80 // link-local fe80::/10
+                    || (segments[0] & 0xffc0) == 0xfec0 // site-local fec0::/10
+                    // NAT64 64:ff9b::/96 embedding an internal IPv4 address
+                    || (segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+                        && Self::is_internal_v4(Ipv4Addr::new(
+                            (segments[6] >> 8) as u8,
+                            segments[6] as u8,
+                            (segments[7] >> 8) as u8,
+                            segments[7] as u8,
+                        )))
+            }
+        }
+    }
+
+    fn is_internal_v4(ip: Ipv4Addr) -> bool {
+        let octets = ip.octets();
+        ip.is_private()
+            || ip.is_loopback()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_multicast()
+            || ip.is_documentation()
+            || octets[0] == 0 // 0.0.0.0/8
+            || (octets[0] == 100 && (octets[1] & 0xc0) == 64) // carrier-grade NAT 100.64.0.0/10
+            || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0) // 192.0.0.0/24
+            || (octets[0] == 198 && (octets[1] & 0xfe) == 18) // benchmarking 198.18.0.0/15
+            || octets[0] >= 240 // reserved 240.0.0.0/4
+    }
+
+    fn parse_cidr(entry: &str) -> Option<(IpAddr, u8)> {
+        let (address, prefix) = match entry.split_once('/') {
+            Some((address, prefix)) => (address, prefix.parse::<u8>().ok()?),
+            None => {
+                let ip = entry.parse::<IpAddr>().ok()?;
+                return Some((ip, if ip.is_ipv4() { 32 } else { 128 }));
+            }
+        };
+        Some((address.parse::<IpAddr>().ok()?, prefix))
+    }
+
+    fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+        match (ip, network) {
+            (IpAddr::V4(ip), IpAddr::V4(network)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - prefix.min(32) as u32)
+                    .unwrap_or(0);
+                (u32::from(ip) & mask) == (u32::from(network) & mask)
+            }
+            (IpAddr::V6(ip), IpAddr::V6(network)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - prefix.min(128) as u32)
+                    .unwrap_or(0);
+                (u128::from(ip) & mask) == (u128::from(network) & mask)
+            }
+            (IpAddr::V6(ip), IpAddr::V4(_)) => ip
+                .to_ipv4_mapped()
+                .is_some_and(|ip| Self::in_network(IpAddr::V4(ip), network, prefix)),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn internal_ipv4_ranges_are_blocked() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "198.18.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(SsrfGuard::is_internal(ip(address)), "{}", address);
+        }
+        for address in ["8.8.8.8", "93.184.216.34", "100.128.0.1", "172.32.0.1"] {
+            assert!(!SsrfGuard::is_internal(ip(address)), "{}", address);
+        }
+    }
+
+    #[test]
+    fn internal_ipv6_ranges_are_blocked() {
+        for address in [
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12::1",
+            "fe80::1",
+            "ff02::1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(SsrfGuard::is_internal(ip(address)), "{}", address);
+        }
+        for address in ["2001:4860:4860::8888", "64:ff9b::808:808"] {
+            assert!(!SsrfGuard::is_internal(ip(address)), "{}", address);
+        }
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_are_checked_as_ipv4() {
+        assert!(SsrfGuard::is_internal(ip("::ffff:127.0.0.1")));
+        assert!(SsrfGuard::is_internal(ip("::ffff:169.254.169.254")));
+        assert!(!SsrfGuard::is_internal(ip("::ffff:8.8.8.8")));
+    }
+
+    #[test]
+    fn allowlist_cidr_ranges_match_addresses() {
+        let (network, prefix) = SsrfGuard::parse_cidr("10.1.0.0/16").unwrap();
+        assert!(SsrfGuard::in_network(ip("10.1.255.1"), network, prefix));
+        assert!(!SsrfGuard::in_network(ip("10.2.0.1"), network, prefix));
+        assert!(SsrfGuard::in_network(
+            ip("::ffff:10.1.0.5"),
+            network,
+            prefix
+        ));
+
+        let (network, prefix) = SsrfGuard::parse_cidr("127.0.0.1").unwrap();
+        assert_eq!(prefix, 32);
+        assert!(SsrfGuard::in_network(ip("127.0.0.1"), network, prefix));
+        assert!(!SsrfGuard::in_network(ip("127.0.0.2"), network, prefix));
+
+        let (network, prefix) = SsrfGuard::parse_cidr("fd00::/8").unwrap();
+        assert!(SsrfGuard::in_network(ip("fd12::1"), network, prefix));
+        assert!(!SsrfGuard::in_network(ip("fe80::1"), network, prefix));
+
+        assert_eq!(SsrfGuard::parse_cidr("internal.example.com"), None);
+        assert_eq!(SsrfGuard::parse_cidr("10.0.0.0/x"), None);
+    }
+
+    #[test]
+    fn allowlist_entries_match_cidr_ranges_and_hostnames() {
+        let allowlist = "10.1.0.0/16, internal.example.com";
+
+        assert!(SsrfGuard::allowlist_allows(
+            allowlist,
+            "anything",
+            ip("10.1.2.3")
+        ));
+        assert!(SsrfGuard::allowlist_allows(
+            allowlist,
+            "API.Internal.Example.com",
+            ip("192.168.1.1")
+        ));
+        assert!(!SsrfGuard::allowlist_allows(
+            allowlist,
+            "other.example.com",
+            ip("192.168.1.1")
+        ));
+        assert!(!SsrfGuard::allowlist_allows(
+            "",
+            "localhost",
+            ip("127.0.0.1")
+        ));
+    }
+
+    #[test]
+    fn internal_ip_literals_are_rejected() {
+        for url in [
+            "http://127.0.0.1/admin",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]:8080/",
+            "http://[::ffff:10.0.0.1]/",
+        ] {
+            let blocked = SsrfGuard::check_ip_literal(&Url::parse(url).unwrap());
+            assert!(blocked.is_err(), "{}", url);
+        }
+        assert_eq!(
+            SsrfGuard::check_ip_literal(&Url::parse("http://10.0.0.1/").unwrap())
+                .unwrap_err()
+                .host,
+            "10.0.0.1"
+        );
+
+        for url in [
+            "https://8.8.8.8/",
+            "http://localhost/",
+            "https://example.com/",
+        ] {
+            // Hostnames are left to the resolver
+            assert!(
+                SsrfGuard::check_ip_literal(&Url::parse(url).unwrap()).is_ok(),
+                "{}",
+                url
+            );
+        }
+    }
+}
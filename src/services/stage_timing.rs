@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use crate::services::metrics::Metrics;
+
+tokio::task_local! {
+    /// Told about each finished stage of the search running in this task.
+    static STAGE_LISTENER: Box<dyn Fn(&str, Duration)>;
+}
+
+/// Times pipeline stages: every stage is observed in the metrics, and reported
+/// to the listener of the task it runs in, if any (e.g. a search job's progress).
+pub struct StageTiming;
+
+impl StageTiming {
+    /// Stages: `optimisation`, `custom_search`, `scraping`, `rendering`,
+    /// `context_trimming`, `answer` and `research_review`.
+    pub fn observe(stage: &str, duration: Duration) {
+        Metrics::observe_stage(stage, duration);
+        let _ = STAGE_LISTENER.try_with(|listener| listener(stage, duration));
+    }
+
+    /// Runs `future` with `listener` told about each stage it finishes.
+    pub async fn report_to<F: std::future::Future>(
+        listener: impl Fn(&str, Duration) + 'static,
+        future: F,
+    ) -> F::Output {
+        STAGE_LISTENER.scope(Box::new(listener), future).await
+    }
+}
@@ -0,0 +1,82 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::constants::config::DEFAULT_OTEL_SERVICE_NAME;
+
+/// Keeps the OTLP exporter alive; call `shutdown` before exiting so buffered
+/// spans are flushed.
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Installs the global `tracing` subscriber. Logs are filtered by `RUST_LOG`
+    /// (defaulting to info), written as JSON lines when `LOG_FORMAT=json`, and
+    /// spans are exported over OTLP/HTTP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+    pub fn init() -> Self {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        let json_output = std::env::var("LOG_FORMAT")
+            .map(|format| format.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+
+        let tracer_provider = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(endpoint) if !endpoint.trim().is_empty() => match Self::tracer_provider() {
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    eprintln!("Failed to create the OTLP exporter: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let otel_layer = tracer_provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_OTEL_SERVICE_NAME))
+        });
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(json_output.then(|| {
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false)
+            }))
+            .with((!json_output).then(tracing_subscriber::fmt::layer))
+            .with(otel_layer)
+            .init();
+
+        Telemetry { tracer_provider }
+    }
+
+    /// Exports spans in batches to the collector at `OTEL_EXPORTER_OTLP_ENDPOINT`
+    /// (e.g. http://localhost:4318), read by the exporter itself.
+    fn tracer_provider() -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()?;
+
+        let mut resource = Resource::builder();
+        if std::env::var("OTEL_SERVICE_NAME").is_err() {
+            resource = resource.with_service_name(DEFAULT_OTEL_SERVICE_NAME);
+        }
+
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build();
+        opentelemetry::global::set_tracer_provider(provider.clone());
+        Ok(provider)
+    }
+
+    pub fn shutdown(self) {
+        if let Some(provider) = self.tracer_provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush spans to the OTLP collector: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use reqwest::{Client, Proxy};
+
+use crate::constants::config::{
+    DEFAULT_CUSTOM_SEARCH_API_VERSION, DEFAULT_CUSTOM_SEARCH_BASE_URL, DEFAULT_GEMINI_API_VERSION,
+    DEFAULT_GEMINI_BASE_URL,
+};
+use crate::constants::utility::log_error;
+
+/// The Google APIs the app talks to.
+#[derive(Debug, Clone, Copy)]
+pub enum Upstream {
+    CustomSearch,
+    Gemini,
+}
+
+impl Upstream {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CustomSearch => "custom_search",
+            Self::Gemini => "gemini",
+        }
+    }
+
+    fn env_prefix(&self) -> &'static str {
+        match self {
+            Self::CustomSearch => "CUSTOM_SEARCH",
+            Self::Gemini => "GEMINI",
+        }
+    }
+}
+
+/// Endpoints, API versions and outbound proxies of the Google APIs, each
+/// overridable with `<UPSTREAM>_BASE_URL`, `<UPSTREAM>_API_VERSION` and
+/// `<UPSTREAM>_PROXY` env variables (e.g. to go through an egress proxy, a
+/// regional endpoint, a caching gateway or a local stand-in).
+pub struct Upstreams;
+
+impl Upstreams {
+    /// The upstream's base URL, without the API version.
+    pub fn base_url(upstream: Upstream) -> String {
+        match upstream {
+            Upstream::CustomSearch => {
+                Self::setting(upstream, "BASE_URL", DEFAULT_CUSTOM_SEARCH_BASE_URL)
+            }
+            Upstream::Gemini => Self::setting(upstream, "BASE_URL", DEFAULT_GEMINI_BASE_URL),
+        }
+    }
+
+    /// The Custom Search JSON API endpoint.
+    pub fn custom_search_url() -> String {
+        format!(
+            "{}/customsearch/{}",
+            Self::base_url(Upstream::CustomSearch),
+            Self::setting(
+                Upstream::CustomSearch,
+                "API_VERSION",
+                DEFAULT_CUSTOM_SEARCH_API_VERSION
+            )
+        )
+    }
+
+    /// A Gemini API model method such as `generateContent`.
+    pub fn gemini_url(model: &str, method: &str) -> String {
+        format!(
+            "{}/{}/models/{}:{}",
+            Self::base_url(Upstream::Gemini),
+            Self::setting(Upstream::Gemini, "API_VERSION", DEFAULT_GEMINI_API_VERSION),
+            model,
+            method
+        )
+    }
+
+    /// A client for the upstream, going through its `<UPSTREAM>_PROXY` if set
+    /// (otherwise the usual `HTTPS_PROXY`/`HTTP_PROXY` env variables apply).
+    pub fn client(upstream: Upstream) -> Client {
+        let builder = Client::builder();
+        let proxy_var = format!("{}_PROXY", upstream.env_prefix());
+
+        let builder = match std::env::var(&proxy_var) {
+            Ok(proxy_url) if !proxy_url.trim().is_empty() => match Proxy::all(proxy_url.trim()) {
+                Ok(proxy) => builder.proxy(proxy),
+                Err(e) => {
+                    log_error(&format!("Invalid {}: {}", proxy_var, e));
+                    builder
+                }
+            },
+            _ => builder,
+        };
+
+        builder.build().unwrap_or_default()
+    }
+
+    fn setting(upstream: Upstream, name: &str, default: &str) -> String {
+        std::env::var(format!("{}_{}", upstream.env_prefix(), name))
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or(default.to_string())
+            .trim()
+            .trim_matches('/')
+            .to_string()
+    }
+}
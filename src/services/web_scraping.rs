@@ -1,16 +1,27 @@
-use actix_web::{HttpResponse, Result};
+use actix_web::Result;
+use encoding_rs::{Encoding, UTF_8};
 use futures_util::stream::{self, StreamExt};
 use regex::Regex;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Response};
 use scraper::{Html, Selector};
 use std::sync::Arc;
-use std::{error::Error, fs, path::Path, time::Instant};
+use std::{fs, path::Path, time::Duration, time::Instant};
 use url::Url;
 
-use crate::constants::config::DISALLOWED_URLS;
+use crate::constants::config::{
+    ALLOWED_CONTENT_TYPES, DEFAULT_SCRAPE_MAX_BODY_BYTES, DEFAULT_SCRAPE_MAX_REDIRECTS,
+    DEFAULT_SCRAPE_TIMEOUT_MS, DISALLOWED_DOMAINS,
+};
 use crate::constants::utility::log_error;
-use crate::models::google_search_models::{SearchResponse, SearchResult};
+use crate::models::google_search_models::{ScrapeSkipReason, SearchResponse, SearchResult};
+use crate::services::domain_filtering::DomainFiltering;
+use crate::services::fixtures::{FixtureUpstream, Fixtures};
+use crate::services::metrics::Metrics;
+use crate::services::page_rendering::PageRendering;
+use crate::services::site_extractors::SiteExtractor;
+use crate::services::ssrf_guard::SsrfGuard;
+use crate::services::stage_timing::StageTiming;
 
 pub struct WebScraping;
 
@@ -28,48 +39,142 @@ impl WebScraping {
         }
     }
 
+    /// The client used for scraping: limited redirects, a request timeout,
+    /// gzip/brotli/deflate decompression and the SSRF guard on DNS resolution
+    /// and every redirect hop. System proxy variables are ignored, as a proxy
+    /// would resolve hosts where the guard can't see them. Panics if the client
+    /// can't be built rather than falling back to one without the guard.
+    pub fn client() -> Client {
+        let max_redirects = std::env::var("SCRAPE_MAX_REDIRECTS")
+            .ok()
+            .and_then(|max| max.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_SCRAPE_MAX_REDIRECTS);
+        let timeout = std::env::var("SCRAPE_TIMEOUT_MS")
+            .ok()
+            .and_then(|timeout| timeout.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SCRAPE_TIMEOUT_MS);
+
+        reqwest::Client::builder()
+            .redirect(SsrfGuard::redirect_policy(max_redirects))
+            .dns_resolver(Arc::new(SsrfGuard))
+            .timeout(Duration::from_millis(timeout))
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
+            .no_proxy()
+            .build()
+            .expect("Failed to build the scraping client")
+    }
+
     pub async fn retrieve_all_website_text_content(body: Vec<SearchResult>) -> Vec<SearchResult> {
         let start_time = Instant::now();
-        let client = Arc::new(reqwest::Client::new());
+        let client = Arc::new(Self::client());
 
         let updated_search_results = stream::iter(body)
-            .map(|mut item| {
+            .map(|item| {
                 let client = Arc::clone(&client);
                 async move {
-                    let cloned_link = item.link.clone();
-
-                    let normalized_url = Self::normalize_url(&cloned_link);
-                    println!("Normalized URL: {}", normalized_url);
-
-                    if DISALLOWED_URLS.contains(&normalized_url.as_str()) {
-                        println!("URL {} is disallowed, skipping", normalized_url);
-                        return item;
-                    }
-                    match Self::scrape_website(&cloned_link, &client).await {
-                        Ok(content) => {
-                            item.website_text_content = Some(content);
-                        }
-                        Err(e) => {
-                            log_error(&format!(
-                                "Failed to scrape website {}: {}",
-                                normalized_url, e
-                            ));
-                        }
-                    }
+                    let item_start_time = Instant::now();
+                    let mut item = Self::scrape_result(item, &client).await;
+                    item.scrape_duration_ms = Some(item_start_time.elapsed().as_millis());
+                    Metrics::record_scrape(
+                        item.skip_reason
+                            .as_ref()
+                            .map(|skip_reason| skip_reason.reason())
+                            .unwrap_or("success"),
+                    );
                     item
                 }
             })
-            .buffer_unordered(10) // Process up to 10 requests concurrently
+            .buffered(10) // Process up to 10 requests concurrently, keeping the search ranking order
             .collect::<Vec<_>>()
             .await;
 
         let end_time = Instant::now();
         let duration = end_time.duration_since(start_time);
-        println!("Full scraping time taken: {:?}", duration);
+        tracing::info!("Full scraping time taken: {:?}", duration);
+        StageTiming::observe("scraping", duration);
 
         updated_search_results
     }
 
+    /// Fills in the scraped content of one result, or why it was skipped.
+    async fn scrape_result(mut item: SearchResult, client: &Client) -> SearchResult {
+        let cloned_link = item.link.clone();
+
+        let normalized_url = Self::normalize_url(&cloned_link);
+        tracing::debug!("Normalized URL: {}", normalized_url);
+
+        if let Some(extractor) = SiteExtractor::for_link(&cloned_link) {
+            match extractor.extract(&cloned_link, client).await {
+                Ok(content) => {
+                    item.website_text_content = Some(content);
+                }
+                Err(e) => {
+                    log_error(&format!(
+                        "Failed to extract {} content from {}: {}",
+                        extractor.name(),
+                        cloned_link,
+                        e
+                    ));
+                    item.skip_reason = Some(ScrapeSkipReason::ExtractionFailed {
+                        message: e.to_string(),
+                    });
+                }
+            }
+            return item;
+        }
+
+        let disallowed = DomainFiltering::host(&cloned_link).is_some_and(|host| {
+            DISALLOWED_DOMAINS
+                .iter()
+                .any(|domain| DomainFiltering::matches(&host, domain))
+        });
+        if disallowed {
+            tracing::info!("URL {} is disallowed, skipping", normalized_url);
+            item.skip_reason = Some(ScrapeSkipReason::Disallowed);
+            return item;
+        }
+        match Self::scrape_website(&cloned_link, client).await {
+            Ok(content) => {
+                item.website_text_content = Some(content);
+            }
+            Err(e) => {
+                log_error(&format!(
+                    "Failed to scrape website {}: {}",
+                    normalized_url, e
+                ));
+                item.skip_reason = Some(e);
+            }
+        }
+
+        let static_text_length = item
+            .website_text_content
+            .as_ref()
+            .map(|content| content.len())
+            .unwrap_or(0);
+        let blocked = matches!(item.skip_reason, Some(ScrapeSkipReason::Blocked { .. }));
+        if !blocked && PageRendering::should_render(&cloned_link, static_text_length) {
+            match PageRendering::render(&cloned_link).await {
+                Ok(html) => {
+                    let rendered_text = Self::extract_text(&html);
+                    if rendered_text.len() > static_text_length {
+                        item.website_text_content = Some(rendered_text);
+                        item.skip_reason = None;
+                    }
+                }
+                Err(e) => {
+                    log_error(&format!(
+                        "Failed to render website {}: {}",
+                        normalized_url, e
+                    ));
+                }
+            }
+        }
+        item
+    }
+
+    /// The results of the mock Custom Search responses in `constants/mock/google_search/test`.
     pub fn get_mock_search_results() -> Result<Vec<SearchResult>, actix_web::Error> {
         let dir_path = "./src/constants/mock/google_search/test";
 
@@ -100,7 +205,7 @@ impl WebScraping {
                 })?;
                 match serde_json::from_str::<SearchResponse>(&contents) {
                     Ok(response) => custom_models.push(response),
-                    Err(e) => println!("Error parsing {}: {}", path.display(), e),
+                    Err(e) => tracing::warn!("Error parsing {}: {}", path.display(), e),
                 }
             }
         }
@@ -114,21 +219,15 @@ impl WebScraping {
         Ok(all_search_items)
     }
 
-    pub async fn retrieve_relevant_search_data_mock() -> Result<HttpResponse, actix_web::Error> {
-        let mock_search_results = Self::get_mock_search_results();
-        let search_results = match mock_search_results {
-            Ok(results) => results,
-            Err(e) => return Err(e),
-        };
-        let updated_search_items = Self::retrieve_all_website_text_content(search_results).await;
-
-        Ok(HttpResponse::Ok().json(updated_search_items))
+    /// Scrapes the mock search results.
+    pub async fn retrieve_relevant_search_data_mock() -> Result<Vec<SearchResult>, actix_web::Error>
+    {
+        let search_results = Self::get_mock_search_results()?;
+        Ok(Self::retrieve_all_website_text_content(search_results).await)
     }
 
-    pub async fn scrape_website(
-        url: &str,
-        client: &Client,
-    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+    #[tracing::instrument(skip(client))]
+    pub async fn scrape_website(url: &str, client: &Client) -> Result<String, ScrapeSkipReason> {
         let start_time = Instant::now();
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -141,9 +240,124 @@ impl WebScraping {
                 .parse::<HeaderValue>()
                 .unwrap(),
         );
-        let response = client.get(url).headers(headers).send().await?;
-        let body = response.text().await?;
-        let document = Html::parse_document(&body);
+        if let Ok(parsed_url) = Url::parse(url) {
+            SsrfGuard::check_ip_literal(&parsed_url).map_err(ScrapeSkipReason::from)?;
+        }
+        let response = Fixtures::send(FixtureUpstream::Pages, client.get(url).headers(headers))
+            .await
+            .map_err(ScrapeSkipReason::from)?;
+        let (mime_type, body) = Self::read_body(url, response, ALLOWED_CONTENT_TYPES).await?;
+
+        let cleaned_text = if mime_type == "text/plain" {
+            Self::clean_text(&body)
+        } else {
+            Self::extract_text(&body)
+        };
+
+        let end_time = Instant::now();
+        let duration = end_time.duration_since(start_time);
+        tracing::info!("Scraping time taken for {}: {:?}", url, duration);
+
+        if cleaned_text.is_empty() {
+            return Err(ScrapeSkipReason::Empty);
+        }
+        Ok(cleaned_text)
+    }
+
+    /// `SCRAPE_MAX_BODY_BYTES`, the most of a page body that is read.
+    pub fn max_body_bytes() -> usize {
+        std::env::var("SCRAPE_MAX_BODY_BYTES")
+            .ok()
+            .and_then(|max| max.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_SCRAPE_MAX_BODY_BYTES)
+    }
+
+    /// Reads a successful response of one of `content_types` (defaulting to
+    /// `text/html` when the header is missing), decoded and truncated at
+    /// `SCRAPE_MAX_BODY_BYTES`. Returns the MIME type and the body.
+    pub async fn read_body(
+        url: &str,
+        mut response: Response,
+        content_types: &[&str],
+    ) -> Result<(String, String), ScrapeSkipReason> {
+        if !response.status().is_success() {
+            return Err(ScrapeSkipReason::HttpStatus {
+                status: response.status().as_u16(),
+            });
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+        let mime_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        if !content_types.contains(&mime_type.as_str()) {
+            return Err(ScrapeSkipReason::UnsupportedContentType {
+                content_type: mime_type,
+            });
+        }
+
+        let max_body_bytes = Self::max_body_bytes();
+        if let Some(content_length) = response.content_length() {
+            if content_length > max_body_bytes as u64 {
+                return Err(ScrapeSkipReason::TooLarge {
+                    bytes: content_length,
+                });
+            }
+        }
+
+        // Stream the body so a page without a Content-Length can't exceed the cap.
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(ScrapeSkipReason::from)? {
+            let remaining = max_body_bytes.saturating_sub(bytes.len());
+            bytes.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+            if bytes.len() >= max_body_bytes {
+                tracing::info!("Body of {} truncated at {} bytes", url, max_body_bytes);
+                break;
+            }
+        }
+
+        Ok((mime_type, Self::decode(&bytes, &content_type)))
+    }
+
+    /// Decodes the body using the charset from the `Content-Type` header, then a
+    /// `<meta>` charset declaration, then a byte order mark, defaulting to UTF-8.
+    fn decode(bytes: &[u8], content_type: &str) -> String {
+        let header_charset = content_type
+            .split(';')
+            .filter_map(|parameter| parameter.trim().strip_prefix("charset="))
+            .next()
+            .map(|charset| charset.trim_matches(|c| c == '"' || c == '\'').to_string());
+
+        let meta_charset = || {
+            let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_string();
+            let meta_regex =
+                Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.-]+)"#).unwrap();
+            meta_regex
+                .captures(&head)
+                .map(|captures| captures[1].to_string())
+        };
+
+        let encoding = header_charset
+            .or_else(meta_charset)
+            .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+            .or_else(|| Encoding::for_bom(bytes).map(|(encoding, _)| encoding))
+            .unwrap_or(UTF_8);
+
+        let (body, _, _) = encoding.decode(bytes);
+        body.into_owned()
+    }
+
+    /// Extracts the cleaned text content of an HTML document.
+    pub fn extract_text(html: &str) -> String {
+        let document = Html::parse_document(html);
 
         let allowed_tags = [
             "h1", "h2", "h3", "h4", "h5", "h6", "p", "span", "a", "article", "sup", "table", "img",
@@ -160,12 +374,7 @@ impl WebScraping {
             }
         }
 
-        let cleaned_text = Self::clean_text(&text_content);
-
-        let end_time = Instant::now();
-        let duration = end_time.duration_since(start_time);
-        println!("Scraping time taken for {}: {:?}", url, duration);
-        Ok(cleaned_text)
+        Self::clean_text(&text_content)
     }
 
     fn clean_text(input: &str) -> String {
@@ -199,3 +408,80 @@ impl WebScraping {
         cleaned_text
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::config::JSON_CONTENT_TYPES;
+
+    fn response(status: u16, content_type: &str, body: Vec<u8>) -> Response {
+        Response::from(
+            http::Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, content_type)
+                .body(body)
+                .unwrap(),
+        )
+    }
+
+    #[actix_web::test]
+    async fn reads_bodies_of_the_allowed_content_types() {
+        let (mime_type, body) = WebScraping::read_body(
+            "https://example.com/thread.json",
+            response(
+                200,
+                "application/json; charset=utf-8",
+                b"{\"a\":1}".to_vec(),
+            ),
+            JSON_CONTENT_TYPES,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(mime_type, "application/json");
+        assert_eq!(body, "{\"a\":1}");
+    }
+
+    #[actix_web::test]
+    async fn rejects_other_content_types_and_failed_requests() {
+        let unsupported = WebScraping::read_body(
+            "https://example.com/thread.json",
+            response(200, "text/html", b"<html></html>".to_vec()),
+            JSON_CONTENT_TYPES,
+        )
+        .await;
+        assert_eq!(
+            unsupported,
+            Err(ScrapeSkipReason::UnsupportedContentType {
+                content_type: "text/html".to_string()
+            })
+        );
+
+        let failed = WebScraping::read_body(
+            "https://example.com/page",
+            response(404, "text/html", Vec::new()),
+            ALLOWED_CONTENT_TYPES,
+        )
+        .await;
+        assert_eq!(failed, Err(ScrapeSkipReason::HttpStatus { status: 404 }));
+    }
+
+    #[actix_web::test]
+    async fn rejects_bodies_over_the_size_limit() {
+        let body = vec![b'a'; DEFAULT_SCRAPE_MAX_BODY_BYTES + 1];
+
+        let too_large = WebScraping::read_body(
+            "https://example.com/video",
+            response(200, "text/plain", body),
+            ALLOWED_CONTENT_TYPES,
+        )
+        .await;
+
+        assert_eq!(
+            too_large,
+            Err(ScrapeSkipReason::TooLarge {
+                bytes: DEFAULT_SCRAPE_MAX_BODY_BYTES as u64 + 1
+            })
+        );
+    }
+}
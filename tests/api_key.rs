@@ -0,0 +1,121 @@
+mod common;
+
+use actix_web::{
+    http::{header::HeaderValue, StatusCode},
+    test::TestRequest,
+};
+use serde_json::{json, Value};
+
+use common::{call, call_with_headers, Upstreams, ANSWER_PROMPT, API_KEY};
+
+#[actix_web::test]
+async fn rejects_requests_without_an_api_key() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/search")
+            .set_json(json!({ "query": "capital of france" })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn rejects_requests_with_the_wrong_api_key() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("x-api-key", "wrong-key"))
+            .set_json(json!({ "query": "hello" })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn rejected_requests_never_reach_the_upstreams() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&[]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    call(
+        TestRequest::post()
+            .uri("/api/search")
+            .insert_header(("x-api-key", "wrong-key"))
+            .set_json(json!({ "query": "capital of france", "optimize_query": false })),
+    )
+    .await;
+
+    assert!(upstreams.custom_search_requests().await.is_empty());
+    assert!(upstreams.completion_prompts("").await.is_empty());
+}
+
+#[actix_web::test]
+async fn accepts_the_api_key_as_a_bearer_token() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("Authorization", format!("Bearer {}", API_KEY)))
+            .set_json(json!({ "query": "hello" })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "world");
+}
+
+#[actix_web::test]
+async fn rejections_are_json_with_a_challenge() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, headers, body) = call_with_headers(
+        TestRequest::post()
+            .uri("/api/search")
+            .insert_header(("Authorization", "Basic dXNlcjpwYXNz"))
+            .set_json(json!({ "query": "capital of france" })),
+    )
+    .await;
+    let error: Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error["error"], "invalid_api_key");
+    assert_eq!(
+        headers.get("WWW-Authenticate").unwrap(),
+        "Bearer realm=\"googlexity\", error=\"invalid_token\""
+    );
+}
+
+#[actix_web::test]
+async fn non_ascii_keys_and_a_missing_api_key_env_are_rejected_without_panicking() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header((
+                "x-api-key",
+                HeaderValue::from_bytes("schlüssel".as_bytes()).unwrap(),
+            ))
+            .set_json(json!({ "query": "hello" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    std::env::remove_var("API_KEY");
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("x-api-key", ""))
+            .set_json(json!({ "query": "hello" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
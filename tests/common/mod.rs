@@ -0,0 +1,308 @@
+#![allow(dead_code)]
+
+use actix_web::{
+    body::to_bytes,
+    http::{header::HeaderMap, StatusCode},
+    test::{self, TestRequest},
+    App,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, MutexGuard};
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+use rust_actix_web_template::{
+    middleware::{
+        cors_middleware::CorsMiddleware, guard_middleware::ApiKeyMiddleware,
+        metrics_middleware::MetricsMiddleware, tracing_middleware::TracingMiddleware,
+    },
+    routes,
+};
+
+pub const API_KEY: &str = "test-api-key";
+pub const ANSWER_PROMPT: &str = "You are a content retrieval AI";
+pub const OPTIMISATION_PROMPT: &str = "You are a search optimisation AI";
+
+/// The handlers read their configuration from env variables, so tests that
+/// point them at different stand-in servers take turns.
+static ENV_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Local stand-ins for Custom Search, Gemini and the scraped websites, with the
+/// env pointing the app at them for as long as this is alive.
+pub struct Upstreams {
+    pub custom_search: MockServer,
+    pub gemini: MockServer,
+    pub websites: MockServer,
+    _env_lock: MutexGuard<'static, ()>,
+}
+
+impl Upstreams {
+    pub async fn start() -> Self {
+        let env_lock = ENV_LOCK.lock().await;
+        let upstreams = Upstreams {
+            custom_search: MockServer::start().await,
+            gemini: MockServer::start().await,
+            websites: MockServer::start().await,
+            _env_lock: env_lock,
+        };
+
+        for (name, value) in [
+            ("API_KEY", API_KEY.to_string()),
+            ("SEARCH_API_KEY", "test-search-key".to_string()),
+            ("SEARCH_ENGINE_ID", "test-engine".to_string()),
+            ("GEMINI_API_KEY", "test-gemini-key".to_string()),
+            ("CUSTOM_SEARCH_BASE_URL", upstreams.custom_search.uri()),
+            ("GEMINI_BASE_URL", upstreams.gemini.uri()),
+            // The stand-in websites are on localhost
+            ("SSRF_ALLOWLIST", "127.0.0.1".to_string()),
+            // Route every link through the generic scraper
+            ("SITE_EXTRACTORS", "none".to_string()),
+        ] {
+            std::env::set_var(name, value);
+        }
+        for name in [
+            "FIXTURE_MODE",
+            "FIXTURE_DIR",
+            "SCRAPE_MAX_BODY_BYTES",
+            "RENDER_BACKEND",
+            "RENDER_SERVICE_URL",
+            "CDP_ENDPOINT",
+            "RENDER_TIMEOUT_MS",
+            "MODEL_REGISTRY",
+            "ALLOWED_MODELS",
+            "TIER_ALLOWED_MODELS",
+            "DOMAIN_POLICIES",
+            "CUSTOM_SEARCH_API_VERSION",
+            "CUSTOM_SEARCH_PROXY",
+            "GEMINI_API_VERSION",
+            "GEMINI_PROXY",
+            "METRICS_TOKEN",
+            "AUTH_MODES",
+            "SEARCH_MAX_CONCURRENCY",
+            "SEARCH_MAX_CONCURRENCY_PER_KEY",
+            "SEARCH_TIER_CONCURRENCY",
+            "SEARCH_QUEUE_SIZE",
+            "SEARCH_QUEUE_TIMEOUT_MS",
+            "CORS_ALLOWED_ORIGINS",
+            "CORS_ALLOWED_HEADERS",
+            "CORS_MAX_AGE",
+            "JWT_ISSUER",
+            "JWT_JWKS_URL",
+            "JWT_KEY_FILE",
+            "JWT_ALGORITHM",
+            "JWT_AUDIENCE",
+            "JWT_SCOPES_CLAIM",
+            "JWT_REQUIRED_SCOPES",
+            "JWT_TIER_CLAIM",
+            "JWT_SCOPE_TIERS",
+            "SEARCH_JOB_WORKERS",
+            "SEARCH_JOB_MAX_PENDING",
+            "SEARCH_JOB_MAX_PENDING_PER_KEY",
+            "SEARCH_JOB_WEBHOOK_SECRET",
+            "MAX_OUTPUT_TOKENS",
+            "INSTAGRAM_OEMBED_TOKEN",
+        ] {
+            std::env::remove_var(name);
+        }
+
+        Mock::given(method("POST"))
+            .and(path_regex(r":countTokens$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "totalTokens": 100 })))
+            .mount(&upstreams.gemini)
+            .await;
+
+        upstreams
+    }
+
+    /// Custom Search answers every query with `links`.
+    pub async fn mock_search_results(&self, links: &[String]) {
+        Mock::given(method("GET"))
+            .and(path("/customsearch/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(search_response(links)))
+            .mount(&self.custom_search)
+            .await;
+    }
+
+    /// Gemini answers prompts containing `prompt` with `text`.
+    pub async fn mock_completion(&self, prompt: &str, text: &str) {
+        Mock::given(method("POST"))
+            .and(path_regex(r":generateContent$"))
+            .and(wiremock::matchers::body_string_contains(prompt))
+            .respond_with(ResponseTemplate::new(200).set_body_json(completion_response(text)))
+            .mount(&self.gemini)
+            .await;
+    }
+
+    /// A page on the stand-in website server, returning its URL.
+    pub async fn mock_page(&self, page_path: &str, text: &str) -> String {
+        Mock::given(method("GET"))
+            .and(path(page_path))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                format!("<html><body><p>{}</p></body></html>", text),
+                "text/html; charset=utf-8",
+            ))
+            .mount(&self.websites)
+            .await;
+        format!("{}{}", self.websites.uri(), page_path)
+    }
+
+    pub async fn custom_search_requests(&self) -> Vec<Request> {
+        self.custom_search
+            .received_requests()
+            .await
+            .unwrap_or_default()
+    }
+
+    /// The `q` parameter of every Custom Search request, in order.
+    pub async fn search_queries(&self) -> Vec<String> {
+        self.custom_search_requests()
+            .await
+            .iter()
+            .filter_map(|request| {
+                request
+                    .url
+                    .query_pairs()
+                    .find(|(name, _)| name == "q")
+                    .map(|(_, value)| value.to_string())
+            })
+            .collect()
+    }
+
+    /// The prompt of every `generateContent` request containing `prompt`.
+    pub async fn completion_prompts(&self, prompt: &str) -> Vec<String> {
+        self.gemini
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|request| request.url.path().ends_with(":generateContent"))
+            .filter_map(|request| serde_json::from_slice::<Value>(&request.body).ok())
+            .filter_map(|body| {
+                body["contents"][0]["parts"][0]["text"]
+                    .as_str()
+                    .map(|text| text.to_string())
+            })
+            .filter(|text| text.contains(prompt))
+            .collect()
+    }
+
+    pub async fn website_requests(&self) -> Vec<Request> {
+        self.websites.received_requests().await.unwrap_or_default()
+    }
+}
+
+/// Sends the request through the app wrapped in `ApiKeyMiddleware`, returning
+/// the status and body whether the middleware or the handler responded.
+pub async fn call(request: TestRequest) -> (StatusCode, String) {
+    let (status, _, body) = call_with_headers(request).await;
+    (status, body)
+}
+
+pub async fn call_with_headers(request: TestRequest) -> (StatusCode, HeaderMap, String) {
+    let app = test::init_service(
+        App::new()
+            .wrap(ApiKeyMiddleware)
+            .wrap(CorsMiddleware::from_env())
+            .wrap(MetricsMiddleware)
+            .wrap(TracingMiddleware)
+            .configure(routes::configure),
+    )
+    .await;
+
+    match test::try_call_service(&app, request.to_request()).await {
+        Ok(response) => {
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = to_bytes(response.into_body()).await.unwrap_or_default();
+            (status, headers, String::from_utf8_lossy(&body).to_string())
+        }
+        Err(e) => {
+            let response = e.error_response();
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body = to_bytes(response.into_body()).await.unwrap_or_default();
+            (status, headers, String::from_utf8_lossy(&body).to_string())
+        }
+    }
+}
+
+/// The subject the test API key is known by, e.g. in `DOMAIN_POLICIES`.
+pub fn api_key_subject() -> String {
+    let hash = Sha256::digest(API_KEY.as_bytes());
+    let key_id: String = hash[..6]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("key:{}", key_id)
+}
+
+pub fn search_request(body: Value) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/search")
+        .insert_header(("x-api-key", API_KEY))
+        .set_json(body)
+}
+
+pub fn search_response(links: &[String]) -> Value {
+    let items: Vec<Value> = links
+        .iter()
+        .enumerate()
+        .map(|(i, link)| {
+            json!({
+                "kind": "customsearch#result",
+                "title": format!("Result {}", i + 1),
+                "htmlTitle": format!("Result {}", i + 1),
+                "link": link,
+                "displayLink": link,
+                "snippet": format!("Snippet of result {}", i + 1),
+                "htmlSnippet": format!("Snippet of result {}", i + 1),
+                "formattedUrl": link,
+                "htmlFormattedUrl": link,
+            })
+        })
+        .collect();
+
+    json!({
+        "kind": "customsearch#search",
+        "url": { "type": "application/json", "template": "" },
+        "queries": {
+            "request": [{
+                "title": "Google Custom Search",
+                "totalResults": links.len().to_string(),
+                "searchTerms": "",
+                "count": links.len(),
+                "startIndex": 1,
+                "inputEncoding": "utf8",
+                "outputEncoding": "utf8",
+                "safe": "off",
+                "cx": "test-engine"
+            }]
+        },
+        "context": { "title": "googlexity" },
+        "searchInformation": {
+            "searchTime": 0.1,
+            "formattedSearchTime": "0.10",
+            "totalResults": links.len().to_string(),
+            "formattedTotalResults": links.len().to_string()
+        },
+        "items": items
+    })
+}
+
+pub fn completion_response(text: &str) -> Value {
+    json!({
+        "candidates": [{
+            "content": { "parts": [{ "text": text }], "role": "model" },
+            "finishReason": "STOP",
+            "index": 0
+        }],
+        "usageMetadata": {
+            "promptTokenCount": 10,
+            "candidatesTokenCount": 5,
+            "totalTokenCount": 15
+        }
+    })
+}
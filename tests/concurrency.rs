@@ -0,0 +1,85 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use common::{
+    call, call_with_headers, completion_response, search_request, Upstreams, ANSWER_PROMPT,
+};
+
+/// Answers slowly enough for searches to overlap.
+async fn mock_slow_answer(upstreams: &Upstreams, delay: Duration) {
+    upstreams.mock_search_results(&[]).await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .and(wiremock::matchers::body_string_contains(ANSWER_PROMPT))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(completion_response("Paris"))
+                .set_delay(delay),
+        )
+        .mount(&upstreams.gemini)
+        .await;
+}
+
+fn search() -> actix_web::test::TestRequest {
+    search_request(json!({ "query": "capital of france", "optimize_query": false }))
+}
+
+#[actix_web::test]
+async fn rejects_searches_over_the_per_key_limit_when_the_queue_is_full() {
+    let upstreams = Upstreams::start().await;
+    mock_slow_answer(&upstreams, Duration::from_millis(300)).await;
+    std::env::set_var("SEARCH_MAX_CONCURRENCY_PER_KEY", "1");
+    std::env::set_var("SEARCH_QUEUE_SIZE", "0");
+
+    let (first, second) = tokio::join!(call_with_headers(search()), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        call_with_headers(search()).await
+    });
+
+    assert_eq!(first.0, StatusCode::OK);
+    assert_eq!(second.0, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(second.1.get("Retry-After").unwrap(), "5");
+    let error: Value = serde_json::from_str(&second.2).unwrap();
+    assert_eq!(error["error"], "queue_full");
+    assert_eq!(error["scope"], "key");
+}
+
+#[actix_web::test]
+async fn queued_searches_run_once_a_slot_frees_up() {
+    let upstreams = Upstreams::start().await;
+    mock_slow_answer(&upstreams, Duration::from_millis(200)).await;
+    std::env::set_var("SEARCH_MAX_CONCURRENCY", "1");
+    std::env::set_var("SEARCH_QUEUE_SIZE", "4");
+    std::env::set_var("SEARCH_QUEUE_TIMEOUT_MS", "5000");
+
+    let ((first, _), (second, _)) = tokio::join!(call(search()), call(search()));
+
+    assert_eq!(first, StatusCode::OK);
+    assert_eq!(second, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn queued_searches_give_up_after_the_timeout() {
+    let upstreams = Upstreams::start().await;
+    mock_slow_answer(&upstreams, Duration::from_millis(1000)).await;
+    std::env::set_var("SEARCH_MAX_CONCURRENCY", "1");
+    std::env::set_var("SEARCH_QUEUE_TIMEOUT_MS", "100");
+
+    let (first, second) = tokio::join!(call(search()), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        call(search()).await
+    });
+
+    assert_eq!(first.0, StatusCode::OK);
+    assert_eq!(second.0, StatusCode::SERVICE_UNAVAILABLE);
+    let error: Value = serde_json::from_str(&second.1).unwrap();
+    assert_eq!(error["error"], "queue_timeout");
+    assert_eq!(error["scope"], "global");
+}
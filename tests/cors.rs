@@ -0,0 +1,92 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::json;
+
+use common::{call_with_headers, Upstreams, API_KEY};
+
+fn preflight(origin: &str) -> TestRequest {
+    TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/api/search")
+        .insert_header(("Origin", origin))
+        .insert_header(("Access-Control-Request-Method", "POST"))
+        .insert_header(("Access-Control-Request-Headers", "content-type,x-api-key"))
+}
+
+fn header(headers: &actix_web::http::header::HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+#[actix_web::test]
+async fn preflights_succeed_without_an_api_key() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var("CORS_ALLOWED_ORIGINS", "https://app.example.com");
+    std::env::set_var("CORS_MAX_AGE", "600");
+
+    let (status, headers, _) = call_with_headers(preflight("https://app.example.com")).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        header(&headers, "access-control-allow-origin").as_deref(),
+        Some("https://app.example.com")
+    );
+    assert!(header(&headers, "access-control-allow-headers")
+        .unwrap()
+        .contains("x-api-key"));
+    assert_eq!(
+        header(&headers, "access-control-max-age").as_deref(),
+        Some("600")
+    );
+}
+
+#[actix_web::test]
+async fn responses_and_rejections_carry_cors_headers() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+    std::env::set_var("CORS_ALLOWED_ORIGINS", "https://*.example.com");
+
+    let request = |key: &str| {
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("Origin", "https://preview-42.example.com"))
+            .insert_header(("x-api-key", key))
+            .set_json(json!({ "query": "hello" }))
+    };
+
+    let (status, headers, _) = call_with_headers(request(API_KEY)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        header(&headers, "access-control-allow-origin").as_deref(),
+        Some("https://preview-42.example.com")
+    );
+    assert!(header(&headers, "access-control-expose-headers")
+        .unwrap()
+        .contains("x-request-id"));
+
+    // The browser can read the 401 instead of seeing a CORS failure
+    let (status, headers, _) = call_with_headers(request("wrong-key")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        header(&headers, "access-control-allow-origin").as_deref(),
+        Some("https://preview-42.example.com")
+    );
+}
+
+#[actix_web::test]
+async fn other_origins_are_not_allowed() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var("CORS_ALLOWED_ORIGINS", "https://app.example.com");
+
+    let (status, headers, _) = call_with_headers(preflight("https://evil.example.net")).await;
+    assert_ne!(status, StatusCode::OK);
+    assert_eq!(header(&headers, "access-control-allow-origin"), None);
+
+    // Without CORS_ALLOWED_ORIGINS there's no CORS layer and preflights need a key
+    std::env::remove_var("CORS_ALLOWED_ORIGINS");
+    let (status, _, _) = call_with_headers(preflight("https://app.example.com")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
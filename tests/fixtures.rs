@@ -0,0 +1,81 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+use common::{call, Upstreams, API_KEY};
+
+/// A fixture directory of its own, removed again when dropped.
+struct FixtureDir(PathBuf);
+
+impl FixtureDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "googlexity-fixtures-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::env::set_var("FIXTURE_DIR", &path);
+        FixtureDir(path)
+    }
+
+    fn recorded_pages(&self) -> usize {
+        std::fs::read_dir(self.0.join("pages"))
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for FixtureDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+async fn scrape(url: &str) -> Value {
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": [url] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    pages[0].clone()
+}
+
+#[actix_web::test]
+async fn replays_recorded_pages_without_network_access() {
+    let upstreams = Upstreams::start().await;
+    let fixture_dir = FixtureDir::new("round-trip");
+    let url = upstreams.mock_page("/recorded", "Recorded page").await;
+
+    std::env::set_var("FIXTURE_MODE", "record");
+    let recorded = scrape(&url).await;
+    assert_eq!(recorded["text"], "Recorded page");
+    assert_eq!(fixture_dir.recorded_pages(), 1);
+
+    upstreams.websites.reset().await;
+    std::env::set_var("FIXTURE_MODE", "replay");
+    let replayed = scrape(&url).await;
+
+    assert_eq!(replayed["text"], "Recorded page");
+    assert!(upstreams.website_requests().await.is_empty());
+}
+
+#[actix_web::test]
+async fn pages_over_the_size_limit_are_not_recorded() {
+    let upstreams = Upstreams::start().await;
+    let fixture_dir = FixtureDir::new("too-large");
+    let url = upstreams.mock_page("/large", &"a".repeat(1024)).await;
+    std::env::set_var("SCRAPE_MAX_BODY_BYTES", "512");
+
+    std::env::set_var("FIXTURE_MODE", "record");
+    let page = scrape(&url).await;
+
+    assert_eq!(page["skip_reason"]["reason"], "too_large");
+    assert_eq!(fixture_dir.recorded_pages(), 0);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+use common::{call, call_with_headers, completion_response, Upstreams, API_KEY};
+
+fn generate_content_request(body: serde_json::Value) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/generate-content")
+        .insert_header(("x-api-key", API_KEY))
+        .set_json(body)
+}
+
+#[actix_web::test]
+async fn returns_the_completion_text() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path(
+            "/v1beta/models/gemini-1.5-flash-latest:generateContent",
+        ))
+        .and(query_param("key", "test-gemini-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("Hello there")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, headers, body) =
+        call_with_headers(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Hello there");
+    assert_eq!(
+        headers.get("x-googlexity-model").unwrap(),
+        "gemini-1.5-flash-latest"
+    );
+}
+
+#[actix_web::test]
+async fn uses_the_requested_model() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1beta/models/gemini-1.5-pro-latest:generateContent"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("From pro")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, body) = call(generate_content_request(
+        json!({ "query": "hello", "model": "gemini-1.5-pro-latest" }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "From pro");
+}
+
+#[actix_web::test]
+async fn reports_gemini_http_errors() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, body) = call(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "HTTP error! status: 500 Internal Server Error");
+}
+
+#[actix_web::test]
+async fn falls_back_to_the_next_model_when_overloaded() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path(
+            "/v1beta/models/gemini-1.5-flash-latest:generateContent",
+        ))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&upstreams.gemini)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("Fallback")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, headers, body) =
+        call_with_headers(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Fallback");
+    assert_eq!(
+        headers.get("x-googlexity-model").unwrap(),
+        "gemini-2.5-flash"
+    );
+}
+
+#[actix_web::test]
+async fn falls_back_to_a_model_the_callers_tier_may_use() {
+    let upstreams = Upstreams::start().await;
+    std::env::set_var(
+        "TIER_ALLOWED_MODELS",
+        json!({ "default": ["gemini-2.5-flash"] }).to_string(),
+    );
+    Mock::given(method("POST"))
+        .and(path("/v1beta/models/gemini-2.5-flash:generateContent"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("Allowed")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, headers, body) =
+        call_with_headers(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Allowed");
+    assert_eq!(
+        headers.get("x-googlexity-model").unwrap(),
+        "gemini-2.5-flash"
+    );
+}
+
+#[actix_web::test]
+async fn rejects_models_the_callers_tier_may_not_use() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var(
+        "TIER_ALLOWED_MODELS",
+        json!({ "default": ["gemini-2.5-pro"] }).to_string(),
+    );
+
+    let (status, body) = call(generate_content_request(
+        json!({ "query": "hello", "model": "gemini-2.0-flash" }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["error"], "model_not_allowed");
+}
+
+#[actix_web::test]
+async fn fails_when_the_prompt_is_blocked() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "promptFeedback": { "blockReason": "SAFETY" }
+        })))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, _) = call(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[actix_web::test]
+async fn uses_the_configured_api_version() {
+    let upstreams = Upstreams::start().await;
+    std::env::set_var("GEMINI_API_VERSION", "v1");
+    Mock::given(method("POST"))
+        .and(path("/v1/models/gemini-1.5-flash-latest:generateContent"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("From v1")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, body) = call(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "From v1");
+}
+
+#[actix_web::test]
+async fn goes_through_the_configured_proxy() {
+    let upstreams = Upstreams::start().await;
+    std::env::set_var("GEMINI_BASE_URL", "http://gemini.invalid");
+    std::env::set_var("GEMINI_PROXY", upstreams.gemini.uri());
+    Mock::given(method("POST"))
+        .and(path(
+            "/v1beta/models/gemini-1.5-flash-latest:generateContent",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion_response("Proxied")))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, body) = call(generate_content_request(json!({ "query": "hello" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Proxied");
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::Value;
+
+use common::{call, Upstreams};
+
+#[actix_web::test]
+async fn health_endpoints_need_no_api_key() {
+    let _upstreams = Upstreams::start().await;
+
+    for path in ["/healthz", "/readyz", "/version"] {
+        let (status, _) = call(TestRequest::get().uri(path)).await;
+        assert_eq!(status, StatusCode::OK, "{}", path);
+    }
+}
+
+#[actix_web::test]
+async fn not_ready_without_the_required_config() {
+    let _upstreams = Upstreams::start().await;
+    std::env::remove_var("GEMINI_API_KEY");
+
+    let (status, body) = call(TestRequest::get().uri("/readyz")).await;
+    let readiness: Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(readiness["ready"], false);
+    assert_eq!(readiness["missing_config"][0], "GEMINI_API_KEY");
+}
+
+#[actix_web::test]
+async fn probes_the_upstreams_when_asked() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, body) = call(TestRequest::get().uri("/readyz?probe=true")).await;
+    let readiness: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(readiness["upstreams"][0]["upstream"], "custom_search");
+    assert_eq!(readiness["upstreams"][1]["reachable"], true);
+
+    std::env::set_var("GEMINI_BASE_URL", "http://127.0.0.1:1");
+    let (status, body) = call(TestRequest::get().uri("/readyz?probe=true")).await;
+    let readiness: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(readiness["upstreams"][1]["reachable"], false);
+}
+
+#[actix_web::test]
+async fn reports_the_version_and_providers() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var("SITE_EXTRACTORS", "reddit,youtube");
+
+    let (_, body) = call(TestRequest::get().uri("/version")).await;
+    let version: Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version["providers"]["llm"], "gemini");
+    assert_eq!(
+        version["providers"]["site_extractors"],
+        serde_json::json!(["reddit", "youtube"])
+    );
+    assert_eq!(version["providers"]["fixture_mode"], "live");
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use common::{call, call_with_headers, Upstreams, API_KEY};
+
+const SECRET: &[u8] = b"a-shared-secret-for-tests";
+
+fn token(secret: &[u8], kid: Option<&str>, claims: Value) -> String {
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = kid.map(|kid| kid.to_string());
+    let mut claims = claims;
+    claims["exp"] = json!(get_current_timestamp() + 600);
+    claims["sub"] = json!("internal-app");
+    encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+}
+
+fn completion_request(token: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/generate-content")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({ "query": "hello" }))
+}
+
+/// Serves the issuer's discovery document and a JWKS with the test secret.
+async fn mock_issuer(upstreams: &Upstreams, jwks_path: &str, kid: &str) -> String {
+    let issuer = upstreams.websites.uri();
+    Mock::given(method("GET"))
+        .and(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "issuer": issuer,
+            "jwks_uri": format!("{}{}", issuer, jwks_path),
+        })))
+        .mount(&upstreams.websites)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(jwks_path))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "keys": [{ "kty": "oct", "kid": kid, "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(SECRET) }]
+        })))
+        .mount(&upstreams.websites)
+        .await;
+    issuer
+}
+
+#[actix_web::test]
+async fn accepts_tokens_signed_with_the_static_key_file() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+    let key_file = std::env::temp_dir().join("googlexity-jwt-test-secret");
+    std::fs::write(&key_file, SECRET).unwrap();
+    std::env::set_var("AUTH_MODES", "jwt");
+    std::env::set_var("JWT_KEY_FILE", &key_file);
+    std::env::set_var("JWT_ALGORITHM", "HS256");
+    std::env::set_var("JWT_ISSUER", "https://issuer.example");
+    std::env::set_var("JWT_AUDIENCE", "googlexity");
+
+    let claims = json!({ "iss": "https://issuer.example", "aud": "googlexity" });
+    let (status, body) = call(completion_request(&token(SECRET, None, claims))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "world");
+
+    let wrong_audience = json!({ "iss": "https://issuer.example", "aud": "another-api" });
+    let (status, body) = call(completion_request(&token(SECRET, None, wrong_audience))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        serde_json::from_str::<Value>(&body).unwrap()["error"],
+        "invalid_token"
+    );
+
+    let wrong_issuer = json!({ "iss": "https://elsewhere.example", "aud": "googlexity" });
+    let (status, _) = call(completion_request(&token(SECRET, None, wrong_issuer))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // API keys are off with AUTH_MODES=jwt
+    let (status, _) = call(completion_request(API_KEY)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn verifies_tokens_against_the_issuers_jwks_alongside_api_keys() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+    let issuer = mock_issuer(&upstreams, "/jwks-discovery.json", "key-1").await;
+    std::env::set_var("AUTH_MODES", "api_key,jwt");
+    std::env::set_var("JWT_ISSUER", &issuer);
+
+    let claims = json!({ "iss": issuer });
+    let (status, _) = call(completion_request(&token(
+        SECRET,
+        Some("key-1"),
+        claims.clone(),
+    )))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = call(completion_request(&token(
+        b"some-other-secret",
+        Some("key-1"),
+        claims.clone(),
+    )))
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(completion_request(&token(SECRET, Some("key-2"), claims))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(completion_request(API_KEY)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn rejects_tokens_missing_the_required_scopes() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+    let issuer = mock_issuer(&upstreams, "/jwks-scopes.json", "key-1").await;
+    std::env::set_var("AUTH_MODES", "jwt");
+    std::env::set_var("JWT_JWKS_URL", format!("{}/jwks-scopes.json", issuer));
+    std::env::set_var("JWT_REQUIRED_SCOPES", "search");
+
+    let read_only = json!({ "scope": "profile email" });
+    let (status, headers, body) =
+        call_with_headers(completion_request(&token(SECRET, Some("key-1"), read_only))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        serde_json::from_str::<Value>(&body).unwrap()["error"],
+        "insufficient_scope"
+    );
+    assert!(headers
+        .get("WWW-Authenticate")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("error=\"insufficient_scope\", scope=\"search\""));
+
+    let with_scope = json!({ "scp": ["profile", "search"] });
+    let (status, _) = call(completion_request(&token(
+        SECRET,
+        Some("key-1"),
+        with_scope,
+    )))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn applies_the_domain_policy_of_the_tokens_subject() {
+    let upstreams = Upstreams::start().await;
+    let allowed = upstreams.mock_page("/allowed", "Allowed page").await;
+    let excluded = upstreams.mock_page("/excluded", "Excluded page").await;
+    let excluded = excluded.replace("127.0.0.1", "localhost");
+    let key_file = std::env::temp_dir().join("googlexity-jwt-domain-policy-secret");
+    std::fs::write(&key_file, SECRET).unwrap();
+    std::env::set_var("AUTH_MODES", "jwt");
+    std::env::set_var("JWT_KEY_FILE", &key_file);
+    std::env::set_var("JWT_ALGORITHM", "HS256");
+    std::env::set_var(
+        "DOMAIN_POLICIES",
+        json!({ "internal-app": { "exclude_domains": ["localhost"] } }).to_string(),
+    );
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", token(SECRET, None, json!({}))),
+            ))
+            .set_json(json!({ "urls": [allowed, excluded] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    let pages = pages.as_array().unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0]["text"], "Allowed page");
+}
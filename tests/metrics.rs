@@ -0,0 +1,62 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::json;
+
+use common::{call, search_request, Upstreams, ANSWER_PROMPT};
+
+#[actix_web::test]
+async fn metrics_do_not_need_an_api_key() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, _) = call(TestRequest::get().uri("/metrics")).await;
+
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn metrics_can_require_a_token() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var("METRICS_TOKEN", "metrics-token");
+
+    let (status, _) = call(TestRequest::get().uri("/metrics")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(
+        TestRequest::get()
+            .uri("/metrics")
+            .insert_header(("Authorization", "Bearer metrics-token")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn searches_are_counted() {
+    let upstreams = Upstreams::start().await;
+    let page = upstreams.mock_page("/paris", "Paris is in France.").await;
+    upstreams.mock_search_results(&[page]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "depthfull_search": true
+    })))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, metrics) = call(TestRequest::get().uri("/metrics")).await;
+    assert!(metrics.contains(r#"googlexity_custom_search_requests_total{outcome="success"}"#));
+    assert!(metrics.contains(r#"googlexity_scrape_results_total{outcome="success"}"#));
+    assert!(metrics.contains(
+        r#"googlexity_gemini_requests_total{method="generateContent",model="gemini-1.5-pro-latest",outcome="success"}"#
+    ));
+    assert!(metrics.contains(
+        r#"googlexity_gemini_tokens_total{kind="prompt",model="gemini-1.5-pro-latest"}"#
+    ));
+    assert!(metrics.contains(r#"googlexity_stage_duration_seconds_count{stage="answer"}"#));
+    assert!(metrics.contains(
+        r#"googlexity_http_request_duration_seconds_count{method="POST",route="/api/search",status="200"}"#
+    ));
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use serde_json::json;
+use tokio::net::TcpListener;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use rust_actix_web_template::services::page_rendering::PageRendering;
+
+use common::Upstreams;
+
+#[actix_web::test]
+async fn closes_the_devtools_tab_when_rendering_times_out() {
+    let upstreams = Upstreams::start().await;
+    // A DevTools websocket that accepts connections but never answers
+    let devtools = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let websocket_url = format!(
+        "ws://{}/devtools/page/tab-1",
+        devtools.local_addr().unwrap()
+    );
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = devtools.accept().await {
+            connections.push(connection);
+        }
+    });
+    Mock::given(method("PUT"))
+        .and(path("/json/new"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "tab-1",
+            "webSocketDebuggerUrl": websocket_url,
+        })))
+        .mount(&upstreams.websites)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/json/close/tab-1"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&upstreams.websites)
+        .await;
+    std::env::set_var("RENDER_BACKEND", "cdp");
+    std::env::set_var("CDP_ENDPOINT", upstreams.websites.uri());
+    std::env::set_var("RENDER_TIMEOUT_MS", "300");
+
+    let page_url = format!("{}/app?a=1&b=2#section", upstreams.websites.uri());
+    let error = PageRendering::render(&page_url).await.unwrap_err();
+
+    assert!(error.to_string().contains("timed out"), "{}", error);
+    let requests = upstreams.website_requests().await;
+    let new_tab = requests
+        .iter()
+        .find(|request| request.url.path() == "/json/new")
+        .unwrap();
+    let requested_url: String =
+        url::form_urlencoded::parse(new_tab.url.query().unwrap().as_bytes())
+            .map(|(name, _)| name.into_owned())
+            .collect();
+    assert_eq!(requested_url, page_url);
+    assert!(requests
+        .iter()
+        .any(|request| request.url.path() == "/json/close/tab-1"));
+}
+
+#[actix_web::test]
+async fn rejects_pages_the_browser_was_redirected_away_from() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("POST"))
+        .and(path("/render/redirected"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "Content-Location",
+                    "http://169.254.169.254/latest/meta-data/",
+                )
+                .set_body_string("<html><body>instance metadata</body></html>"),
+        )
+        .mount(&upstreams.websites)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/render/direct"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string("<html><body>Rendered</body></html>"),
+        )
+        .mount(&upstreams.websites)
+        .await;
+    std::env::set_var("RENDER_BACKEND", "service");
+    let page_url = format!("{}/app", upstreams.websites.uri());
+
+    std::env::set_var(
+        "RENDER_SERVICE_URL",
+        format!("{}/render/redirected", upstreams.websites.uri()),
+    );
+    let error = PageRendering::render(&page_url).await.unwrap_err();
+    assert!(error.to_string().contains("redirected"), "{}", error);
+
+    std::env::set_var(
+        "RENDER_SERVICE_URL",
+        format!("{}/render/direct", upstreams.websites.uri()),
+    );
+    let html = PageRendering::render(&page_url).await.unwrap();
+    assert!(html.contains("Rendered"));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::{json, Value};
+
+use common::{api_key_subject, call, Upstreams, API_KEY};
+
+#[actix_web::test]
+async fn leaves_out_urls_excluded_by_the_callers_domain_policy() {
+    // The caller's own policy takes precedence over its tier's
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_page("/allowed", "Allowed page").await;
+    upstreams.mock_page("/excluded", "Excluded page").await;
+    std::env::set_var(
+        "DOMAIN_POLICIES",
+        json!({
+            api_key_subject(): { "exclude_domains": ["localhost"] },
+            "tier:default": { "exclude_domains": ["127.0.0.1"] },
+        })
+        .to_string(),
+    );
+    let port = upstreams.websites.address().port();
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": [
+                format!("http://127.0.0.1:{}/allowed", port),
+                format!("http://localhost:{}/excluded", port),
+            ] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    let pages = pages.as_array().unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(
+        pages[0]["link"],
+        format!("http://127.0.0.1:{}/allowed", port)
+    );
+    assert_eq!(pages[0]["text"], "Allowed page");
+    let requests = upstreams.website_requests().await;
+    assert!(requests
+        .iter()
+        .all(|request| request.url.path() != "/excluded"));
+}
+
+#[actix_web::test]
+async fn callers_without_a_policy_use_their_tiers() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_page("/allowed", "Allowed page").await;
+    std::env::set_var(
+        "DOMAIN_POLICIES",
+        json!({ "tier:default": { "exclude_domains": ["localhost"] } }).to_string(),
+    );
+    let port = upstreams.websites.address().port();
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": [
+                format!("http://127.0.0.1:{}/allowed", port),
+                format!("http://localhost:{}/excluded", port),
+            ] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(pages.as_array().unwrap().len(), 1);
+    assert_eq!(pages[0]["text"], "Allowed page");
+}
+
+#[actix_web::test]
+async fn instagram_links_fall_back_to_generic_scraping_without_a_token() {
+    let _upstreams = Upstreams::start().await;
+    std::env::set_var("SITE_EXTRACTORS", "instagram");
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": ["https://www.instagram.com/p/abc123/"] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(pages[0]["skip_reason"], json!({ "reason": "disallowed" }));
+}
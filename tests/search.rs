@@ -0,0 +1,383 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use common::{call, search_request, Upstreams, ANSWER_PROMPT, API_KEY, OPTIMISATION_PROMPT};
+
+fn links(count: usize) -> Vec<String> {
+    (1..=count)
+        .map(|i| format!("https://example{}.com/page", i))
+        .collect()
+}
+
+#[actix_web::test]
+async fn searches_the_query_as_is_without_optimisation() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(2)).await;
+    upstreams
+        .mock_completion(ANSWER_PROMPT, "Paris is the capital of France.")
+        .await;
+
+    let (status, body) = call(search_request(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Paris is the capital of France.");
+    assert_eq!(upstreams.search_queries().await, vec!["capital of france"]);
+    assert!(upstreams
+        .completion_prompts(OPTIMISATION_PROMPT)
+        .await
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn searches_each_optimised_query() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(1)).await;
+    upstreams
+        .mock_completion(OPTIMISATION_PROMPT, "capital of france;paris population")
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(json!({ "query": "tell me about paris" }))).await;
+
+    assert_eq!(status, StatusCode::OK);
+    let optimisation_prompts = upstreams.completion_prompts(OPTIMISATION_PROMPT).await;
+    assert_eq!(optimisation_prompts.len(), 1);
+    assert!(optimisation_prompts[0].ends_with("tell me about paris"));
+    assert_eq!(
+        upstreams.search_queries().await,
+        vec!["capital of france", "paris population"]
+    );
+}
+
+#[actix_web::test]
+async fn max_optimizations_caps_the_search_queries() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(1)).await;
+    upstreams
+        .mock_completion(OPTIMISATION_PROMPT, "first query;second query;third query")
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(
+        json!({ "query": "tell me about paris", "max_optimizations": 2 }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        upstreams.search_queries().await,
+        vec!["first query", "second query"]
+    );
+}
+
+#[actix_web::test]
+async fn max_results_limits_the_results_in_the_prompt() {
+    let upstreams = Upstreams::start().await;
+    let links = links(3);
+    upstreams.mock_search_results(&links).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "max_results": 1
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert_eq!(answer_prompts.len(), 1);
+    assert!(answer_prompts[0].contains(&links[0]));
+    assert!(!answer_prompts[0].contains(&links[1]));
+    assert!(!answer_prompts[0].contains(&links[2]));
+}
+
+#[actix_web::test]
+async fn depthful_search_scrapes_each_result() {
+    let upstreams = Upstreams::start().await;
+    let page = upstreams
+        .mock_page("/paris", "Paris has been the capital since 987.")
+        .await;
+    upstreams.mock_search_results(&[page]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "depthfull_search": true
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upstreams.website_requests().await.len(), 1);
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert!(answer_prompts[0].contains("Paris has been the capital since 987."));
+}
+
+#[actix_web::test]
+async fn results_are_not_scraped_without_depthful_search() {
+    let upstreams = Upstreams::start().await;
+    let page = upstreams
+        .mock_page("/paris", "Paris has been the capital since 987.")
+        .await;
+    upstreams.mock_search_results(&[page]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(upstreams.website_requests().await.is_empty());
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert!(!answer_prompts[0].contains("Paris has been the capital since 987."));
+}
+
+#[actix_web::test]
+async fn disallowed_urls_are_skipped_when_scraping() {
+    let upstreams = Upstreams::start().await;
+    let page = upstreams.mock_page("/paris", "Paris is in France.").await;
+    upstreams
+        .mock_search_results(&[
+            "https://www.reddit.com/r/france/comments/1".to_string(),
+            page,
+        ])
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "depthfull_search": true
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert!(answer_prompts[0].contains(r#""skip_reason":{"reason":"disallowed"}"#));
+    assert!(answer_prompts[0].contains("Paris is in France."));
+}
+
+#[actix_web::test]
+async fn failed_pages_are_skipped_with_a_reason() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&upstreams.websites)
+        .await;
+    upstreams
+        .mock_search_results(&[format!("{}/missing", upstreams.websites.uri())])
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, body) = call(search_request(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "depthfull_search": true
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "answer");
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert!(answer_prompts[0].contains(r#""skip_reason":{"reason":"http_status","status":404}"#));
+}
+
+#[actix_web::test]
+async fn custom_search_failures_fail_the_search() {
+    let upstreams = Upstreams::start().await;
+    Mock::given(method("GET"))
+        .and(path("/customsearch/v1"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&upstreams.custom_search)
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(upstreams.completion_prompts(ANSWER_PROMPT).await.is_empty());
+}
+
+#[actix_web::test]
+async fn gemini_failures_fail_the_search() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(1)).await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&upstreams.gemini)
+        .await;
+
+    let (status, _) = call(search_request(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[actix_web::test]
+async fn max_cost_includes_the_cost_of_a_full_length_answer() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(1)).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "Paris").await;
+    let search = || {
+        search_request(json!({
+            "query": "capital of france",
+            "model": "gemini-2.5-pro",
+            "optimize_query": false,
+            "max_cost": 0.05,
+        }))
+    };
+
+    // 8192 output tokens at $10 per million is over budget on its own.
+    let (status, _) = call(search()).await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert!(upstreams.completion_prompts(ANSWER_PROMPT).await.is_empty());
+
+    std::env::set_var("MAX_OUTPUT_TOKENS", "1000");
+    let (status, body) = call(search()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "Paris");
+    let requests = upstreams.gemini.received_requests().await.unwrap();
+    assert!(
+        requests
+            .iter()
+            .any(|request| String::from_utf8_lossy(&request.body)
+                .contains("\"maxOutputTokens\":1000"))
+    );
+}
+
+#[actix_web::test]
+async fn max_cost_includes_the_grounding_fee() {
+    let upstreams = Upstreams::start().await;
+    std::env::set_var("MAX_OUTPUT_TOKENS", "100");
+
+    // The answer fits in $0.03, but grounding adds $0.035 per query.
+    let (status, body) = call(search_request(json!({
+        "query": "capital of france",
+        "mode": "grounded",
+        "max_cost": 0.03,
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert!(body.contains("exceeds max_cost"));
+    let requests = upstreams.gemini.received_requests().await.unwrap();
+    assert!(!requests
+        .iter()
+        .any(|request| request.url.path().ends_with(":generateContent")));
+}
+
+#[actix_web::test]
+async fn short_prompts_are_not_sent_to_count_tokens() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&links(2)).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+
+    let (status, _) = call(search_request(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let requests = upstreams.gemini.received_requests().await.unwrap();
+    assert!(!requests
+        .iter()
+        .any(|request| request.url.path().ends_with(":countTokens")));
+}
+
+#[actix_web::test]
+async fn trims_the_lowest_ranked_content_to_fit_the_context_window() {
+    let upstreams = Upstreams::start().await;
+    let first = upstreams
+        .mock_page("/paris", "Paris has been the capital since 987.")
+        .await;
+    let second = upstreams
+        .mock_page("/history", &"Long history of France. ".repeat(2_000))
+        .await;
+    upstreams
+        .mock_search_results(&[first, second.clone()])
+        .await;
+    upstreams.mock_completion(ANSWER_PROMPT, "answer").await;
+    // About 4 bytes per token, so the count shrinks as the prompt is trimmed.
+    Mock::given(method("POST"))
+        .and(path_regex(r":countTokens$"))
+        .respond_with(|request: &wiremock::Request| {
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "totalTokens": request.body.len() / 4 }))
+        })
+        .with_priority(1)
+        .mount(&upstreams.gemini)
+        .await;
+    std::env::set_var(
+        "MODEL_REGISTRY",
+        json!([{
+            "name": "tiny-model",
+            "context_window": 8_192 + 4_000,
+            "input_price": 0.1,
+            "output_price": 0.4,
+        }])
+        .to_string(),
+    );
+
+    let (status, body) = call(search_request(json!({
+        "query": "capital of france",
+        "model": "tiny-model",
+        "optimize_query": false,
+        "depthfull_search": true,
+        "response_format": "json",
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let context_trim = &response["context_trim"];
+    assert_eq!(context_trim["token_limit"], 4_000);
+    assert_eq!(context_trim["dropped_website_content"], json!([second]));
+    assert_eq!(context_trim["dropped_results"], json!([]));
+    assert!(context_trim["original_tokens"].as_u64().unwrap() > 4_000);
+    assert!(context_trim["final_tokens"].as_u64().unwrap() <= 4_000);
+
+    let answer_prompts = upstreams.completion_prompts(ANSWER_PROMPT).await;
+    assert!(answer_prompts[0].contains("Paris has been the capital since 987."));
+    assert!(!answer_prompts[0].contains("Long history of France."));
+}
+
+#[actix_web::test]
+async fn summarize_checks_the_model_before_scraping() {
+    let upstreams = Upstreams::start().await;
+    let page = upstreams
+        .mock_page("/paris", "Paris is the capital of France.")
+        .await;
+    std::env::set_var(
+        "TIER_ALLOWED_MODELS",
+        json!({ "default": ["gemini-2.5-pro"] }).to_string(),
+    );
+
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/summarize")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": [page], "model": "gemini-2.0-flash" })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(upstreams.website_requests().await.is_empty());
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{encode, get_current_timestamp, Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+use wiremock::{
+    matchers::{body_string_contains, method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use rust_actix_web_template::constants::config::DEFAULT_RATE_LIMIT_TIER;
+use rust_actix_web_template::services::concurrency::ConcurrencyLimits;
+
+use common::{
+    api_key_subject, call, call_with_headers, completion_response, Upstreams, ANSWER_PROMPT,
+    API_KEY,
+};
+
+fn create_job(body: Value) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/search/jobs")
+        .insert_header(("x-api-key", API_KEY))
+        .set_json(body)
+}
+
+/// Polls the job until `done` holds for it, for up to 5 seconds.
+async fn wait_for_job(status_url: &str, done: impl Fn(&Value) -> bool) -> Value {
+    for _ in 0..100 {
+        let (status, body) = call(
+            TestRequest::get()
+                .uri(status_url)
+                .insert_header(("x-api-key", API_KEY)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let job: Value = serde_json::from_str(&body).unwrap();
+        if done(&job) {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("search job at {} didn't finish", status_url);
+}
+
+#[actix_web::test]
+async fn runs_the_search_in_the_background_and_reports_the_result() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&[]).await;
+    upstreams
+        .mock_completion(ANSWER_PROMPT, "Paris is the capital of France.")
+        .await;
+
+    let (status, headers, body) = call_with_headers(create_job(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "response_format": "markdown",
+    })))
+    .await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let created: Value = serde_json::from_str(&body).unwrap();
+    let status_url = created["status_url"].as_str().unwrap();
+    assert_eq!(headers.get("Location").unwrap(), status_url);
+    assert_eq!(created["status"], "queued");
+
+    let job = wait_for_job(status_url, |job| job["status"] == "succeeded").await;
+    assert_eq!(job["id"], created["id"]);
+    assert_eq!(job["result"]["answer"], "Paris is the capital of France.");
+    assert!(job["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|stage| stage["stage"] == "answer"));
+    assert!(job["finished_at"].is_string());
+}
+
+#[actix_web::test]
+async fn delivers_a_signed_webhook_when_the_job_finishes() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&[]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "Paris").await;
+    Mock::given(method("POST"))
+        .and(path("/hooks/search"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&upstreams.websites)
+        .await;
+    std::env::set_var("SEARCH_JOB_WEBHOOK_SECRET", "webhook-secret");
+
+    let (status, body) = call(create_job(json!({
+        "query": "capital of france",
+        "optimize_query": false,
+        "callback_url": format!("{}/hooks/search", upstreams.websites.uri()),
+    })))
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let created: Value = serde_json::from_str(&body).unwrap();
+
+    let job = wait_for_job(created["status_url"].as_str().unwrap(), |job| {
+        job["webhook"].is_object()
+    })
+    .await;
+    assert_eq!(job["webhook"]["delivered"], true);
+    assert_eq!(job["webhook"]["attempts"], 1);
+
+    let requests = upstreams.website_requests().await;
+    let webhook = requests
+        .iter()
+        .find(|request| request.url.path() == "/hooks/search")
+        .unwrap();
+    let timestamp = webhook.headers.get("x-googlexity-timestamp").unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"webhook-secret").unwrap();
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(&webhook.body);
+    let expected: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    assert_eq!(
+        webhook.headers.get("x-googlexity-signature").unwrap(),
+        format!("sha256={}", expected).as_str()
+    );
+
+    let payload: Value = serde_json::from_slice(&webhook.body).unwrap();
+    assert_eq!(payload["id"], created["id"]);
+    assert_eq!(payload["status"], "succeeded");
+    assert_eq!(payload["result"]["answer"], "Paris");
+}
+
+#[actix_web::test]
+async fn rejects_bad_callbacks_and_unknown_jobs() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, body) = call(create_job(json!({
+        "query": "capital of france",
+        "callback_url": "https://example.com/hooks/search",
+    })))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["error"], "webhooks_not_configured");
+
+    std::env::set_var("SEARCH_JOB_WEBHOOK_SECRET", "webhook-secret");
+    let (status, body) = call(create_job(json!({
+        "query": "capital of france",
+        "callback_url": "http://10.0.0.1/hooks/search",
+    })))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error["error"], "invalid_callback_url");
+
+    let (status, _) = call(
+        TestRequest::get()
+            .uri("/api/search/jobs/does-not-exist")
+            .insert_header(("x-api-key", API_KEY)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn jobs_wait_for_the_callers_concurrency_slot() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&[]).await;
+    upstreams.mock_completion(ANSWER_PROMPT, "Paris").await;
+    std::env::set_var("SEARCH_MAX_CONCURRENCY_PER_KEY", "1");
+    std::env::set_var("SEARCH_QUEUE_SIZE", "0");
+    std::env::set_var("SEARCH_QUEUE_TIMEOUT_MS", "100");
+    let held = ConcurrencyLimits::acquire(&api_key_subject(), DEFAULT_RATE_LIMIT_TIER)
+        .await
+        .unwrap();
+
+    let (status, body) = call(create_job(
+        json!({ "query": "capital of france", "optimize_query": false }),
+    ))
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let created: Value = serde_json::from_str(&body).unwrap();
+    let status_url = created["status_url"].as_str().unwrap();
+
+    // Well past the interactive queue timeout, the job is still waiting
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let job = wait_for_job(status_url, |_| true).await;
+    assert_eq!(job["status"], "queued");
+
+    drop(held);
+    let job = wait_for_job(status_url, |job| job["finished_at"].is_string()).await;
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["result"]["answer"], "Paris");
+}
+
+#[actix_web::test]
+async fn caps_pending_jobs_per_caller() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_search_results(&[]).await;
+    Mock::given(method("POST"))
+        .and(path_regex(r":generateContent$"))
+        .and(body_string_contains(ANSWER_PROMPT))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(completion_response("Paris"))
+                .set_delay(Duration::from_millis(1000)),
+        )
+        .mount(&upstreams.gemini)
+        .await;
+    let key_file = std::env::temp_dir().join("googlexity-search-jobs-jwt-secret");
+    std::fs::write(&key_file, b"a-shared-secret-for-tests").unwrap();
+    std::env::set_var("AUTH_MODES", "api_key,jwt");
+    std::env::set_var("JWT_KEY_FILE", &key_file);
+    std::env::set_var("JWT_ALGORITHM", "HS256");
+    std::env::set_var("SEARCH_JOB_MAX_PENDING_PER_KEY", "1");
+    let body = json!({ "query": "capital of france", "optimize_query": false });
+
+    let (status, _) = call(create_job(body.clone())).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, _, error) = call_with_headers(create_job(body.clone())).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let error: Value = serde_json::from_str(&error).unwrap();
+    assert_eq!(error["error"], "queue_full");
+
+    let claims = json!({ "sub": "internal-app", "exp": get_current_timestamp() + 600 });
+    let token = encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(b"a-shared-secret-for-tests"),
+    )
+    .unwrap();
+    let (status, _) = call(
+        TestRequest::post()
+            .uri("/api/search/jobs")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use actix_web::{http::StatusCode, test::TestRequest};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use rust_actix_web_template::{
+    models::google_search_models::ScrapeSkipReason, services::web_scraping::WebScraping,
+};
+
+use common::{call, Upstreams, API_KEY};
+
+#[actix_web::test]
+async fn skips_pages_on_internal_hosts() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_page("/admin", "Internal admin page").await;
+    std::env::remove_var("SSRF_ALLOWLIST");
+    let port = upstreams.websites.address().port();
+
+    let (status, body) = call(
+        TestRequest::post()
+            .uri("/api/scrape")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "urls": [format!("http://localhost:{}/admin", port)] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    let pages: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        pages[0]["skip_reason"],
+        json!({ "reason": "blocked", "host": "localhost" })
+    );
+    assert!(pages[0]["text"].is_null());
+    assert!(upstreams.website_requests().await.is_empty());
+}
+
+#[actix_web::test]
+async fn connection_errors_from_the_resolver_are_reported_as_blocked() {
+    let upstreams = Upstreams::start().await;
+    std::env::remove_var("SSRF_ALLOWLIST");
+    let url = format!(
+        "http://localhost:{}/page",
+        upstreams.websites.address().port()
+    );
+
+    let error = WebScraping::client().get(&url).send().await.unwrap_err();
+
+    assert_eq!(
+        ScrapeSkipReason::from(error),
+        ScrapeSkipReason::Blocked {
+            host: "localhost".to_string()
+        }
+    );
+}
+
+#[actix_web::test]
+async fn redirects_to_internal_addresses_are_blocked() {
+    let upstreams = Upstreams::start().await;
+    let port = upstreams.websites.address().port();
+    // localhost stands in for a public host, 127.0.0.1 stays internal
+    std::env::set_var("SSRF_ALLOWLIST", "localhost");
+    Mock::given(method("GET"))
+        .and(path("/moved"))
+        .respond_with(
+            ResponseTemplate::new(302)
+                .insert_header("Location", format!("http://127.0.0.1:{}/admin", port)),
+        )
+        .mount(&upstreams.websites)
+        .await;
+    upstreams.mock_page("/admin", "Internal admin page").await;
+
+    let result = WebScraping::scrape_website(
+        &format!("http://localhost:{}/moved", port),
+        &WebScraping::client(),
+    )
+    .await;
+
+    assert_eq!(
+        result,
+        Err(ScrapeSkipReason::Blocked {
+            host: "127.0.0.1".to_string()
+        })
+    );
+    let requests = upstreams.website_requests().await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url.path(), "/moved");
+}
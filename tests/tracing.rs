@@ -0,0 +1,68 @@
+mod common;
+
+use actix_web::test::TestRequest;
+use serde_json::json;
+
+use common::{call_with_headers, Upstreams, API_KEY};
+
+fn request_id(headers: &actix_web::http::header::HeaderMap) -> Option<String> {
+    headers
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+#[actix_web::test]
+async fn generates_a_request_id_for_each_request() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+
+    let request = || {
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("x-api-key", API_KEY))
+            .set_json(json!({ "query": "hello" }))
+    };
+    let (_, first_headers, _) = call_with_headers(request()).await;
+    let (_, second_headers, _) = call_with_headers(request()).await;
+
+    let first = request_id(&first_headers).expect("missing x-request-id");
+    let second = request_id(&second_headers).expect("missing x-request-id");
+    assert_eq!(first.len(), 36);
+    assert_ne!(first, second);
+}
+
+#[actix_web::test]
+async fn keeps_the_callers_request_id() {
+    let upstreams = Upstreams::start().await;
+    upstreams.mock_completion("hello", "world").await;
+
+    let (_, headers, _) = call_with_headers(
+        TestRequest::post()
+            .uri("/api/generate-content")
+            .insert_header(("x-api-key", API_KEY))
+            .insert_header(("x-request-id", "checkout-1234"))
+            .set_json(json!({ "query": "hello" })),
+    )
+    .await;
+
+    assert_eq!(request_id(&headers).as_deref(), Some("checkout-1234"));
+}
+
+#[actix_web::test]
+async fn replaces_unsafe_request_ids_and_tags_rejected_requests() {
+    let _upstreams = Upstreams::start().await;
+
+    let (status, headers, _) = call_with_headers(
+        TestRequest::post()
+            .uri("/api/search")
+            .insert_header(("x-request-id", "id with spaces\""))
+            .set_json(json!({ "query": "capital of france" })),
+    )
+    .await;
+
+    assert_eq!(status.as_u16(), 401);
+    let request_id = request_id(&headers).expect("missing x-request-id");
+    assert_ne!(request_id, "id with spaces\"");
+    assert_eq!(request_id.len(), 36);
+}